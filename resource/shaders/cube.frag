@@ -1,11 +1,38 @@
 #version 450
 
 layout(location = 0) in vec2 txl_crd;
+layout(location = 1) in vec3 v_tint;
+layout(location = 2) flat in vec3 v_anim;  // frame count, frame time, frame height
+layout(location = 3) flat in vec4 v_tile;  // the texture in the atlas: x, y, width, height
+layout(location = 4) in float v_ao;  // ambient occlusion: 0 (dark) to 3 (open)
 
 layout(location = 0) out vec4 f_color;
 
 layout(set = 0, binding = 0) uniform sampler2D tex;
 
+// the pixels more transparent than this are cut out; 0 for the opaque and translucent blocks
+layout(constant_id = 0) const float ALPHA_CUTOFF = 0.0;
+
+layout(push_constant) uniform PushConstants {
+    vec3 origin;  // the position the vertices are relative to (see cube.vert)
+    float time;  // seconds since the game started
+} constants;
+
 void main() {
-    f_color = texture(tex, txl_crd);
+    // the texture repeats on every block of a merged quad
+    vec2 crd = v_tile.xy + fract(txl_crd)*v_tile.zw;
+
+    // animated textures move down their strip of frames
+    if (v_anim.x > 1.0) {
+        crd.y += mod(floor(constants.time/v_anim.y), v_anim.x)*v_anim.z;
+    }
+
+    // the corners next to blocks get darker
+    float light = 0.4 + 0.2*v_ao;
+
+    vec4 color = texture(tex, crd);
+    if (color.a < ALPHA_CUTOFF) {
+        discard;
+    }
+    f_color = vec4(color.rgb * v_tint * light, color.a);
 }
\ No newline at end of file
@@ -1,9 +1,22 @@
 #version 450
 
-layout(location = 0) in vec3 position;
-layout(location = 1) in vec2 txtr_crd;
+layout(location = 0) in uvec2 data;  // the packed vertex (see CubeVtx)
 
 layout(location = 0) out vec2 txl_crd;
+layout(location = 1) out vec3 v_tint;
+layout(location = 2) flat out vec3 v_anim;
+layout(location = 3) flat out vec4 v_tile;
+layout(location = 4) out float v_ao;
+
+struct Tile {
+    vec4 place;  // the first frame in the atlas: x, y, width, height
+    vec4 anim;  // frame count, frame time, frame height
+};
+
+// every texture in the atlas, by tile id (see TextureAtlas::tiles)
+layout(set = 0, binding = 1) uniform Tiles {
+    Tile tiles[512];  // MAX_TILES
+};
 
 layout(set = 1, binding = 0) uniform Matrix {
     mat4 proj;
@@ -11,7 +24,50 @@ layout(set = 1, binding = 0) uniform Matrix {
     mat4 world;
 } matrix;
 
+layout(push_constant) uniform PushConstants {
+    vec3 origin;  // the position the vertices are relative to: the chunk (or the entity)
+    float time;  // seconds since the game started
+} constants;
+
+// the directions the texture goes along on each face (see orientation.rs)
+const vec3 U[6] = vec3[](vec3(1, 0, 0), vec3(1, 0, 0), vec3(0, 0, -1), vec3(0, 0, 1), vec3(1, 0, 0), vec3(-1, 0, 0));
+const vec3 V[6] = vec3[](vec3(0, 0, -1), vec3(0, 0, 1), vec3(0, -1, 0), vec3(0, -1, 0), vec3(0, -1, 0), vec3(0, -1, 0));
+// the corners of a texture: top left, top right, bottom right and bottom left
+const vec2 CORNERS[4] = vec2[](vec2(0, 0), vec2(1, 0), vec2(1, 1), vec2(0, 1));
+
+// the tint colours at the corners of the climate range: cold-dry, cold-wet, hot-dry, hot-wet; a copy
+// of the ones in climate.rs, whose tests check that they are the same
+const vec3 GRASS_COLORS[4] = vec3[](vec3(0.56, 0.69, 0.55), vec3(0.38, 0.62, 0.46), vec3(0.75, 0.72, 0.33), vec3(0.33, 0.78, 0.20));
+const vec3 FOLIAGE_COLORS[4] = vec3[](vec3(0.45, 0.58, 0.45), vec3(0.28, 0.50, 0.36), vec3(0.62, 0.60, 0.20), vec3(0.22, 0.64, 0.10));
+const float CLIMATE_STEPS = 31.0;
+
+// the tint colour of the climate, like Climate::blend
+vec3 tint(vec3 colors[4], vec2 climate) {
+    vec3 cold = mix(colors[0], colors[1], climate.y);
+    vec3 hot = mix(colors[2], colors[3], climate.y);
+    return mix(cold, hot, climate.x);
+}
+
 void main() {
-    gl_Position = matrix.proj * matrix.view * matrix.world * vec4(position, 1.0);
-    txl_crd = txtr_crd;
-}
\ No newline at end of file
+    vec3 position = vec3(data.x & 0x7FFu, (data.x >> 11) & 0x7FFu, data.y & 0x7FFu)/16.0;
+    uint face = (data.x >> 22) & 7u;
+    uint turn = (data.x >> 27) & 7u;
+    uint tinted = (data.y >> 11) & 3u;
+    Tile tile = tiles[(data.y >> 13) & 0x1FFu];
+    vec2 climate = vec2((data.y >> 22) & 31u, data.y >> 27)/CLIMATE_STEPS;
+
+    gl_Position = matrix.proj * matrix.view * matrix.world * vec4(constants.origin + position, 1.0);
+
+    // the texture goes along the u and v of the face, and repeats on every block (see cube.frag)
+    uint corner = turn & 3u;
+    bool mirrored = (turn & 4u) != 0u;
+    vec2 top_left = CORNERS[corner];
+    vec2 top_right = CORNERS[(corner + (mirrored ? 3u : 1u)) % 4u];
+    vec2 bottom_left = CORNERS[(corner + (mirrored ? 1u : 3u)) % 4u];
+    txl_crd = top_left + dot(position, U[face])*(top_right - top_left) + dot(position, V[face])*(bottom_left - top_left);
+
+    v_tint = tinted == 1u ? tint(GRASS_COLORS, climate) : tinted == 2u ? tint(FOLIAGE_COLORS, climate) : vec3(1.0);
+    v_anim = tile.anim.xyz;
+    v_tile = tile.place;
+    v_ao = float((data.x >> 25) & 3u);
+}
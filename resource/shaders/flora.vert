@@ -0,0 +1,40 @@
+#version 450
+
+layout(location = 0) in vec3 position;  // within the chunk
+layout(location = 1) in vec2 txtr_crd;
+layout(location = 2) in uint tile;  // the tile id of the texture
+layout(location = 3) in vec3 tint;
+
+layout(location = 0) out vec2 txl_crd;
+layout(location = 1) out vec3 v_tint;
+layout(location = 2) flat out vec3 v_anim;
+layout(location = 3) flat out vec4 v_tile;
+
+struct Tile {
+    vec4 place;  // the first frame in the atlas: x, y, width, height
+    vec4 anim;  // frame count, frame time, frame height
+};
+
+// every texture in the atlas, by tile id (see cube.vert)
+layout(set = 0, binding = 1) uniform Tiles {
+    Tile tiles[512];  // MAX_TILES
+};
+
+layout(set = 1, binding = 0) uniform Matrix {
+    mat4 proj;
+    mat4 view;
+    mat4 world;
+} matrix;
+
+layout(push_constant) uniform PushConstants {
+    vec3 origin;  // the position the vertices are relative to: the chunk
+    float time;  // seconds since the game started
+} constants;
+
+void main() {
+    gl_Position = matrix.proj * matrix.view * matrix.world * vec4(constants.origin + position, 1.0);
+    txl_crd = txtr_crd;
+    v_tint = tint;
+    v_anim = tiles[tile].anim.xyz;
+    v_tile = tiles[tile].place;
+}
\ No newline at end of file
@@ -0,0 +1,32 @@
+#version 450
+
+layout(location = 0) in vec2 txl_crd;
+layout(location = 1) in vec3 v_tint;
+layout(location = 2) flat in vec3 v_anim;  // frame count, frame time, frame height
+layout(location = 3) flat in vec4 v_tile;  // the texture in the atlas: x, y, width, height
+layout(location = 4) in vec2 v_flow;  // the direction the liquid flows in along the texture
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D tex;
+
+layout(push_constant) uniform PushConstants {
+    vec3 origin;  // the position the vertices are relative to (see cube.vert)
+    float time;  // seconds since the game started
+} constants;
+
+const float FLOW_SPEED = 0.5;  // in textures per second
+
+void main() {
+    // the texture scrolls the way the liquid flows, and repeats within its tile
+    vec2 crd = v_tile.xy + fract(txl_crd - v_flow*constants.time*FLOW_SPEED)*v_tile.zw;
+
+    // animated textures move down their strip of frames
+    if (v_anim.x > 1.0) {
+        crd.y += mod(floor(constants.time/v_anim.y), v_anim.x)*v_anim.z;
+    }
+
+    // blended over what is behind it by its alpha (see the translucent pass)
+    vec4 color = texture(tex, crd);
+    f_color = vec4(color.rgb * v_tint, color.a);
+}
@@ -1,9 +1,15 @@
-use crate::mesh::mesh::MeshType;
+use crate::mesh::mesh::{MeshType, Layer};
 use std::fmt::{Debug, Formatter};
 use std::fmt;
-use std::rc::Rc;
-use crate::texture::TextureAtlas;
-use crate::block::BlockState::Luminosity;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use crate::generator::climate::Tint;
+use crate::orientation::{Orientation, Rotation};
+use crate::shape::Shape;
+use crate::orientation::Facing;
+
+use serde::{Serialize, Deserialize};
 
 // stores block info
 // TODO: dynamic texture will be called through the Block class
@@ -12,24 +18,124 @@ use crate::block::BlockState::Luminosity;
 pub struct Block {
     // Rc<RefCell<dyn Mesh<Vertex=V>>>
     pub mesh: MeshType,  // the parent mesh
+    pub shape: Shape,  // the boxes the block is made of (for cube meshes)
     pub id: &'static str,  // block id
-    pub texture: &'static [[u16; 2]],  // texture info
+    pub name: &'static str,  // display name
+    pub texture: [&'static str; 6],  // texture name of each face
     pub texture_coord: Vec<[[f32; 2]; 4]>,  // texture coordinate info
-    pub state: u8,  // block state info TODO
+    pub tiles: [u32; 6],  // the tile of each face in the atlas (see TextureAtlas::tile)
+    pub connected: &'static [u32],  // the tiles of the connected texture variants of each face; empty if it doesn't connect
+    pub tint: Tint,  // biome tinting (grass, leaves)
+    pub state: BlockState,  // block state info
+    pub properties: &'static [BlockTag],  // the typed properties stored in the state's custom tags
+    pub rotation: Rotation,  // how the block gets oriented when placed
+    pub parts: &'static [Part],  // the parts of a multi-block structure (doors, beds); empty for a single block
+    pub toggle: Option<&'static str>,  // the Nibble property that flips between 0 and 1 when the block is used
     pub transparent: bool, // TODO: TEMPORARY
+    pub layer: Layer,  // the pass the block is drawn in
+    pub falls: bool,  // falls down when there is nothing below it (sand, gravel)
+    pub hardness: f32,  // negative for indestructible blocks (bedrock)
+}
+
+// a part of a multi-block structure
+// - part 0 is the block that got placed, the rest are at their offset from it (rotated with the
+//   orientation of the structure)
+// - every part is the same block with its own textures, and its index in the block state
+#[derive(Clone, Debug)]
+pub struct Part {
+    pub offset: [i32; 3],
+    pub texture: [&'static str; 6],
+    pub texture_coord: Vec<[[f32; 2]; 4]>,
+    pub tiles: [u32; 6],
 }
 
+// blocks are loaded from the definition files (see blockdef.rs)
 impl Block {
-    // instantiate a new block
-    pub fn new(mesh: MeshType, txtr: Rc<TextureAtlas>, id: &'static str, texture: &'static [[u16; 2]], state: u8, transparent: bool) -> Self {
-        let top = txtr.texture_coord( texture[0][0], texture[0][1]);
-        let bottom = txtr.texture_coord( texture[1][0], texture[1][1]);
-        let left = txtr.texture_coord( texture[2][0], texture[2][1]);
-        let right = txtr.texture_coord( texture[3][0], texture[3][1]);
-        let front = txtr.texture_coord( texture[4][0], texture[4][1]);
-        let back = txtr.texture_coord( texture[5][0], texture[5][1]);
+    // the block as the part of its structure with the given index; none if it has no such part
+    pub fn part(&self, index: u8) -> Option<Block> {
+        let part = self.parts.get(index as usize)?;
+        let mut block = self.clone();
+        block.texture = part.texture;
+        block.texture_coord = part.texture_coord.clone();
+        block.tiles = part.tiles;
+        block.state.part = index;
+        Some(block)
+    }
+
+    // the position and the index of every part of the structure the block at pos belongs to; just
+    // the block itself if it isn't a structure
+    pub fn structure(&self, pos: [i64; 3]) -> Vec<([i64; 3], u8)> {
+        let orientation = self.state.orientation;
+        let own = match self.parts.get(self.state.part as usize) {
+            Some(part) => orientation.offset(part.offset),
+            None => return vec![(pos, 0)],
+        };
+        self.parts.iter().enumerate().map(|(index, part)| {
+            let offset = orientation.offset(part.offset);
+            let position = [
+                pos[0]-own[0] as i64+offset[0] as i64,
+                pos[1]-own[1] as i64+offset[1] as i64,
+                pos[2]-own[2] as i64+offset[2] as i64,
+            ];
+            (position, index as u8)
+        }).collect()
+    }
+
+    // whether the toggle property of the block is on (e.g. an open door)
+    pub fn toggled(&self) -> bool {
+        match self.toggle {
+            Some(toggle) => self.property::<u8>(toggle).is_ok_and(|value| value != 0),
+            None => false,
+        }
+    }
+
+    // how far a liquid flowed from its source: 0 for the source, up to 7 (the "level" property);
+    // 0 for everything else
+    pub fn level(&self) -> u8 {
+        self.property::<u8>("level").map_or(0, |level| level.min(7))
+    }
+
+    // the orientation the block is meshed with; open doors are turned a quarter around
+    pub fn orientation(&self) -> Orientation {
+        match self.state.orientation {
+            Orientation::Facing(facing) if self.shape == Shape::Door && self.toggled() => Orientation::Facing(match facing {
+                Facing::North => Facing::East,
+                Facing::East => Facing::South,
+                Facing::South => Facing::West,
+                Facing::West => Facing::North,
+                vertical => vertical,
+            }),
+            orientation => orientation,
+        }
+    }
+
+    // the value of a property; fails if the block doesn't declare it with that type
+    pub fn property<T: Property>(&self, name: &str) -> Result<T, PropertyError> {
+        let tag = self.declaration(name)?;
+        self.state.tag(&[name])
+            .and_then(T::from_value)
+            .ok_or(PropertyError::WrongType { block: self.id, tag })
+    }
+
+    // sets the value of a property; fails if the block doesn't declare it with that type
+    pub fn set_property<T: Property>(&mut self, name: &str, value: T) -> Result<(), PropertyError> {
+        let tag = self.declaration(name)?;
+        let value = value.into_value();
+        if !tag.accepts(&value) {
+            return Err(match value {
+                TagValue::Nibble(value) => PropertyError::OutOfRange { block: self.id, tag, value: value as i64 },
+                _ => PropertyError::WrongType { block: self.id, tag },
+            });
+        }
+        self.state.tags.insert(name.to_string(), value);
+        Ok(())
+    }
 
-        Self { mesh, id, texture, state, texture_coord: vec![top, bottom, left, right, front, back], transparent }
+    fn declaration(&self, name: &str) -> Result<BlockTag, PropertyError> {
+        self.properties.iter()
+            .find(|tag| tag.name() == name)
+            .copied()
+            .ok_or_else(|| PropertyError::Undeclared { block: self.id, name: name.to_string() })
     }
 
     // // creates the new block in the world
@@ -45,12 +151,15 @@ impl Debug for Block {
             .field("mesh", match &self.mesh {
                 MeshType::Cube => &"Cube",
                 MeshType::Flora => &"Flora",
+                MeshType::Liquid => &"Liquid",
             })
             .field("id", &self.id)
             .finish()
     }
 }
 
+// the declaration of a typed block property, e.g. `Nibble("growth")`
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum BlockTag {
     Nibble(&'static str),
     Integer(&'static str),
@@ -58,35 +167,398 @@ pub enum BlockTag {
     String(&'static str),
 }
 
-pub enum BlockState {
-    Luminosity(u8)
+impl BlockTag {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlockTag::Nibble(name) | BlockTag::Integer(name) | BlockTag::Float(name) | BlockTag::String(name) => name,
+        }
+    }
+
+    // the value of the property on a newly created block
+    pub fn default_value(&self) -> TagValue {
+        match self {
+            BlockTag::Nibble(_) => TagValue::Nibble(0),
+            BlockTag::Integer(_) => TagValue::Integer(0),
+            BlockTag::Float(_) => TagValue::Float(0.0),
+            BlockTag::String(_) => TagValue::String(String::new()),
+        }
+    }
+
+    // whether the value has the type of the property (and nibbles are within [0, 15])
+    pub fn accepts(&self, value: &TagValue) -> bool {
+        match (self, value) {
+            (BlockTag::Nibble(_), TagValue::Nibble(value)) => *value <= 15,
+            (BlockTag::Integer(_), TagValue::Integer(_)) => true,
+            (BlockTag::Float(_), TagValue::Float(_)) => true,
+            (BlockTag::String(_), TagValue::String(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+// the rust types of the block properties; u8 is a nibble
+pub trait Property: Sized {
+    fn from_value(value: &TagValue) -> Option<Self>;
+    fn into_value(self) -> TagValue;
+}
+
+impl Property for u8 {
+    fn from_value(value: &TagValue) -> Option<Self> {
+        if let TagValue::Nibble(value) = value {Some(*value)} else {None}
+    }
+
+    fn into_value(self) -> TagValue {
+        TagValue::Nibble(self)
+    }
+}
+
+impl Property for i32 {
+    fn from_value(value: &TagValue) -> Option<Self> {
+        if let TagValue::Integer(value) = value {Some(*value)} else {None}
+    }
+
+    fn into_value(self) -> TagValue {
+        TagValue::Integer(self)
+    }
+}
+
+impl Property for f32 {
+    fn from_value(value: &TagValue) -> Option<Self> {
+        if let TagValue::Float(value) = value {Some(*value)} else {None}
+    }
+
+    fn into_value(self) -> TagValue {
+        TagValue::Float(self)
+    }
+}
+
+impl Property for String {
+    fn from_value(value: &TagValue) -> Option<Self> {
+        if let TagValue::String(value) = value {Some(value.clone())} else {None}
+    }
+
+    fn into_value(self) -> TagValue {
+        TagValue::String(self)
+    }
+}
+
+#[derive(Debug)]
+pub enum PropertyError {
+    Undeclared { block: &'static str, name: String },
+    WrongType { block: &'static str, tag: BlockTag },
+    OutOfRange { block: &'static str, tag: BlockTag, value: i64 },
+}
+
+impl fmt::Display for PropertyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PropertyError::Undeclared { block, name } => write!(f, "block \"{}\" has no property \"{}\"", block, name),
+            PropertyError::WrongType { block, tag } => write!(f, "property \"{}\" of block \"{}\" is a {:?}", tag.name(), block, tag),
+            PropertyError::OutOfRange { block, tag, value } => write!(f, "{} is out of range for property \"{}\" of block \"{}\"", value, tag.name(), block),
+        }
+    }
+}
+
+// Block State
+// - the standard properties all have a default, so only the ones that differ need to be given
+// - custom tags can nest other custom tags
+// - comparable and hashable, so the same states can be shared as palette entries
+//
+// BlockState::new()
+//     .liquid(false)  // omittable, since it is the default
+//     .luminosity(5)
+//     .orientation(Orientation::Axis(Axis::X))
+//     .nest("custom tag")
+//         .val("itm1", TagValue::Nibble(3))
+//         .val("type", "type 1")
+//         .build()
+//     .build()
+//
+// BlockState {
+//     liquid: false,
+//     luminosity: 5,
+//     orientation: Axis(X),
+//     tags: {
+//         "custom tag": {
+//             "itm1": Nibble(3),
+//             "type": String("type 1"),
+//         }
+//     }
+// }
+
+pub type Tags = BTreeMap<String, TagValue>;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum TagValue {
+    Nibble(u8),  // within [0, 15]
+    Integer(i32),
+    Float(f32),
+    String(String),
+    Compound(Tags),  // nested tags
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default, Serialize, Deserialize)]
+pub struct BlockState {
+    pub liquid: bool,
+    pub luminosity: u8,  // light emission in [0, 15]
+    pub orientation: Orientation,
+    #[serde(default)]
+    pub part: u8,  // the part of a multi-block structure the block is (see Part)
+    pub tags: Tags,  // custom tags
+}
+
+pub struct BlockStateBuilder {
+    state: BlockState,
+}
+
+// builds the custom tag `name`; `build` goes back to the parent builder
+// - nothing in the game nests tags yet; they are for the blocks that will keep their own data
+#[allow(dead_code)]
+pub struct TagBuilder<P: TagParent> {
+    parent: P,
+    name: String,
+    tags: Tags,
+}
+
+#[allow(dead_code)]
+pub trait TagParent {
+    fn insert(&mut self, name: String, value: TagValue);
+}
+
+impl BlockState {
+    #[allow(clippy::new_ret_no_self)]  // the builder is the way to make a state (see above)
+    pub fn new() -> BlockStateBuilder {
+        BlockStateBuilder { state: BlockState::default() }
+    }
+
+    // the custom tag at the given path (e.g. &["custom tag", "itm1"])
+    pub fn tag(&self, path: &[&str]) -> Option<&TagValue> {
+        let (last, nests) = path.split_last()?;
+        let mut tags = &self.tags;
+        for nest in nests {
+            match tags.get(*nest) {
+                Some(TagValue::Compound(nested)) => tags = nested,
+                _ => return None,
+            }
+        }
+        tags.get(*last)
+    }
+}
+
+impl BlockStateBuilder {
+    pub fn liquid(mut self, liquid: bool) -> Self {
+        self.state.liquid = liquid;
+        self
+    }
+
+    pub fn luminosity(mut self, luminosity: u8) -> Self {
+        self.state.luminosity = luminosity.min(15);
+        self
+    }
+
+    pub fn val<V: Into<TagValue>>(mut self, name: &str, value: V) -> Self {
+        self.state.tags.insert(name.to_string(), value.into().clamped());
+        self
+    }
+
+    #[allow(dead_code)]  // see TagBuilder
+    pub fn nest(self, name: &str) -> TagBuilder<Self> {
+        TagBuilder { parent: self, name: name.to_string(), tags: Tags::new() }
+    }
+
+    pub fn build(self) -> BlockState {
+        self.state
+    }
+}
+
+#[allow(dead_code)]
+impl<P: TagParent> TagBuilder<P> {
+    pub fn val<V: Into<TagValue>>(mut self, name: &str, value: V) -> Self {
+        self.tags.insert(name.to_string(), value.into().clamped());
+        self
+    }
+
+    pub fn nest(self, name: &str) -> TagBuilder<Self> {
+        TagBuilder { parent: self, name: name.to_string(), tags: Tags::new() }
+    }
+
+    pub fn build(self) -> P {
+        let mut parent = self.parent;
+        parent.insert(self.name, TagValue::Compound(self.tags));
+        parent
+    }
+}
+
+impl TagParent for BlockStateBuilder {
+    fn insert(&mut self, name: String, value: TagValue) {
+        self.state.tags.insert(name, value);
+    }
+}
+
+impl<P: TagParent> TagParent for TagBuilder<P> {
+    fn insert(&mut self, name: String, value: TagValue) {
+        self.tags.insert(name, value);
+    }
+}
+
+// floats are compared by their bits so that the tags can be hashed
+impl PartialEq for TagValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (TagValue::Nibble(a), TagValue::Nibble(b)) => a == b,
+            (TagValue::Integer(a), TagValue::Integer(b)) => a == b,
+            (TagValue::Float(a), TagValue::Float(b)) => a.to_bits() == b.to_bits(),
+            (TagValue::String(a), TagValue::String(b)) => a == b,
+            (TagValue::Compound(a), TagValue::Compound(b)) => a == b,
+            _ => false,
+        }
+    }
 }
 
-fn priv_block_state() -> BlockState {
-    Luminosity(4)
+impl Eq for TagValue {}
+
+impl Hash for TagValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        match self {
+            TagValue::Nibble(value) => value.hash(state),
+            TagValue::Integer(value) => value.hash(state),
+            TagValue::Float(value) => value.to_bits().hash(state),
+            TagValue::String(value) => value.hash(state),
+            TagValue::Compound(value) => value.hash(state),
+        }
+    }
 }
 
-/*
-Block State Descriptor
+impl TagValue {
+    // nibbles are clamped to 15, like the luminosity
+    fn clamped(self) -> Self {
+        match self {
+            TagValue::Nibble(value) => TagValue::Nibble(value.min(15)),
+            value => value,
+        }
+    }
+}
 
-BlockState::new()
-    .liquid(False)  // ommitable, since it is false; all properties has a default value (if mandatory)
-    .luminosity(5)
-    .nest("custom tag")
-        .val("itm1", Itm_Tag)
-        .val("type 1")
-        .build()
-    .build()
+impl From<i32> for TagValue {
+    fn from(value: i32) -> Self {
+        TagValue::Integer(value)
+    }
+}
+
+impl From<f32> for TagValue {
+    fn from(value: f32) -> Self {
+        TagValue::Float(value)
+    }
+}
+
+impl From<&str> for TagValue {
+    fn from(value: &str) -> Self {
+        TagValue::String(value.to_string())
+    }
+}
 
+impl From<String> for TagValue {
+    fn from(value: String) -> Self {
+        TagValue::String(value)
+    }
+}
 
-Block {
-    liquid: False,
-    luminosity: 8,
-    custom_tag: {
-        itm1: Itm_Tag,
-        typ: "type 1",
+// a plain full block for the tests; the fields a test cares about are set over it
+#[cfg(test)]
+pub fn test_block(id: &'static str) -> Block {
+    Block {
+        mesh: MeshType::Cube,
+        shape: Shape::Full,
+        id,
+        name: id,
+        texture: [id; 6],
+        texture_coord: vec![[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]; 6],
+        tiles: [0; 6],
+        connected: &[],
+        tint: Tint::None,
+        state: BlockState::default(),
+        properties: &[],
+        rotation: Rotation::None,
+        parts: &[],
+        toggle: None,
+        transparent: id == "air",
+        layer: Layer::Opaque,
+        falls: false,
+        hardness: 1.0,
     }
 }
 
-*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+
+    fn hash(state: &BlockState) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        state.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn parts_have_their_own_tiles() {
+        let part = |tile| Part { offset: [0, tile as i32, 0], texture: ["door"; 6], texture_coord: Vec::new(), tiles: [tile; 6] };
+        let door = Block { parts: Box::leak(vec![part(0), part(1)].into_boxed_slice()), ..test_block("door") };
+
+        let top = door.part(1).unwrap();
+        assert_eq!((top.tiles, top.state.part), ([1; 6], 1));
+        assert!(door.part(2).is_none());
+        assert!(test_block("stone").part(0).is_none());
+    }
 
+    #[test]
+    fn builder_defaults() {
+        let state = BlockState::new().build();
+        assert_eq!(state, BlockState::default());
+        assert!(!state.liquid);
+        assert_eq!(state.luminosity, 0);
+        assert!(state.tags.is_empty());
+    }
+
+    #[test]
+    fn builder_clamps_nibbles() {
+        let state = BlockState::new()
+            .luminosity(20)
+            .val("growth", TagValue::Nibble(20))
+            .nest("inner").val("stage", TagValue::Nibble(16)).build()
+            .build();
+        assert_eq!(state.luminosity, 15);
+        assert_eq!(state.tag(&["growth"]), Some(&TagValue::Nibble(15)));
+        assert_eq!(state.tag(&["inner", "stage"]), Some(&TagValue::Nibble(15)));
+    }
+
+    #[test]
+    fn builder_nests_tags() {
+        let state = BlockState::new()
+            .val("count", 3)
+            .nest("custom tag")
+                .val("itm1", "sword")
+                .nest("enchants").val("sharpness", 2.5).build()
+            .build()
+            .build();
+        assert_eq!(state.tag(&["count"]), Some(&TagValue::Integer(3)));
+        assert_eq!(state.tag(&["custom tag", "itm1"]), Some(&TagValue::String("sword".to_string())));
+        assert_eq!(state.tag(&["custom tag", "enchants", "sharpness"]), Some(&TagValue::Float(2.5)));
+        assert_eq!(state.tag(&["custom tag", "missing"]), None);
+        assert_eq!(state.tag(&["count", "itm1"]), None);
+    }
+
+    #[test]
+    fn equal_states_hash_the_same() {
+        let build = |moisture: f32| BlockState::new()
+            .liquid(true)
+            .val("moisture", moisture)
+            .nest("inner").val("name", "water").build()
+            .build();
+        assert_eq!(build(0.5), build(0.5));
+        assert_eq!(hash(&build(0.5)), hash(&build(0.5)));
+        assert_ne!(build(0.5), build(0.75));
+        assert_ne!(build(0.0), build(-0.0));
+        assert_ne!(BlockState::new().val("a", 1).build(), BlockState::new().val("a", 1.0).build());
+    }
+}
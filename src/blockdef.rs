@@ -0,0 +1,345 @@
+use crate::block::{Block, BlockState, BlockTag, Part};
+use crate::mesh::mesh::{MeshType, Layer};
+use crate::texture::{TextureAtlas, CONNECTED_VARIANTS};
+use crate::generator::climate::Tint;
+use crate::orientation::Rotation;
+use crate::shape::Shape;
+
+use serde::Deserialize;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::iter;
+use std::path::{Path, PathBuf};
+
+
+pub const BLOCK_DIR: &str = "resource/blocks";
+const MAX_LIGHT: u8 = 15;
+
+
+// Block Definitions
+// - every block is described by a RON file in BLOCK_DIR (see the README in there for the schema)
+// - the files are read once at startup, so adding a block never needs any code changes
+// - any mistake in a file is reported with the file and the field it is in, or its line and column
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlockDefinition {
+    id: String,
+    name: String,
+    mesh: MeshType,
+    #[serde(default)]
+    shape: Shape,
+    textures: FaceTextures,
+    #[serde(default)]
+    tint: Tint,
+    #[serde(default)]
+    transparent: bool,
+    #[serde(default)]
+    layer: Option<Layer>,  // defaults to the layer of the mesh (see default_layer)
+    #[serde(default)]
+    liquid: bool,
+    #[serde(default)]
+    falls: bool,
+    #[serde(default)]
+    light: u8,
+    hardness: f32,
+    #[serde(default)]
+    connected: bool,
+    #[serde(default)]
+    rotation: Rotation,
+    #[serde(default)]
+    properties: Vec<PropertyDefinition>,
+    #[serde(default)]
+    parts: Vec<PartDefinition>,
+    #[serde(default)]
+    toggle: String,  // the name of a Nibble property; empty if the block can't be used
+}
+
+// another block of a multi-block structure, at its offset from the block that got placed
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PartDefinition {
+    offset: [i32; 3],
+    textures: FaceTextures,
+}
+
+// the typed properties each block of this kind carries (see BlockTag)
+#[derive(Deserialize)]
+enum PropertyDefinition {
+    Nibble(String),
+    Integer(String),
+    Float(String),
+    String(String),
+}
+
+// the texture names of each face; a specific face overrides `side`, which overrides `all`
+// - a face that is left out is an empty name
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+struct FaceTextures {
+    all: String,
+    side: String,  // left, right, front and back
+    top: String,
+    bottom: String,
+    left: String,
+    right: String,
+    front: String,
+    back: String,
+}
+
+#[derive(Debug)]
+pub struct DefinitionError {
+    pub file: PathBuf,
+    pub field: Option<String>,
+    pub position: Option<(usize, usize)>,  // the line and column in the file
+    pub message: String,
+}
+
+impl fmt::Display for DefinitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.field, self.position) {
+            (Some(field), _) => write!(f, "{}: field `{}`: {}", self.file.display(), field, self.message),
+            (None, Some((line, col))) => write!(f, "{}:{}:{}: {}", self.file.display(), line, col, self.message),
+            (None, None) => write!(f, "{}: {}", self.file.display(), self.message),
+        }
+    }
+}
+
+impl FaceTextures {
+    // the texture name of each face in the order of CubeFace, paired with the field it came from
+    fn resolve(&self) -> [(&'static str, &str); 6] {
+        fn or<'a>(face: &'a str, fallback: &'a str) -> &'a str {
+            if face.is_empty() {fallback} else {face}
+        }
+        let side = or(&self.side, &self.all);
+        [
+            ("textures.top", or(&self.top, &self.all)),
+            ("textures.bottom", or(&self.bottom, &self.all)),
+            ("textures.left", or(&self.left, side)),
+            ("textures.right", or(&self.right, side)),
+            ("textures.front", or(&self.front, side)),
+            ("textures.back", or(&self.back, side)),
+        ]
+    }
+}
+
+// loads every block definition in dir, keyed by block id
+pub fn load_blocks(dir: &Path, txtr: &TextureAtlas) -> Result<HashMap<&'static str, Block>, DefinitionError> {
+    let mut files = fs::read_dir(dir)
+        .map_err(|e| error(dir, None, e.to_string()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ron"))
+        .collect::<Vec<_>>();
+    // sorted so that the errors (e.g. on duplicate ids) are always reported on the same file
+    files.sort();
+
+    let mut blocks = HashMap::new();
+    let mut sources: HashMap<&'static str, PathBuf> = HashMap::new();
+
+    for file in files {
+        let source = fs::read_to_string(&file).map_err(|e| error(&file, None, e.to_string()))?;
+        let def = parse(&file, &source)?;
+
+        if def.id.is_empty() {
+            return Err(error(&file, Some("id"), String::from("must not be empty")));
+        }
+        if let Some(other) = sources.get(def.id.as_str()) {
+            return Err(error(&file, Some("id"), format!("\"{}\" is already defined in {}", def.id, other.display())));
+        }
+        if def.light > MAX_LIGHT {
+            return Err(error(&file, Some("light"), format!("{} is above the maximum of {}", def.light, MAX_LIGHT)));
+        }
+        if !def.hardness.is_finite() {
+            return Err(error(&file, Some("hardness"), String::from("must be a finite number")));
+        }
+
+        // the texture names are leaked once for all the faces and parts of the block
+        let mut names = HashMap::new();
+        let textures = face_textures(&file, &def.textures, "", txtr, &mut names)?;
+        let mut connected = Vec::new();
+        if def.connected {
+            for (i, &(field, name)) in def.textures.resolve().iter().enumerate() {
+                match txtr.connected_tiles(name) {
+                    Some(variants) => connected.extend(variants),
+                    None => {
+                        println!("Warning: {}", error(&file, Some(field), format!("no connected texture \"{}_connected\"; the face won't connect", name)));
+                        connected.extend(iter::repeat_n(textures.tiles[i], CONNECTED_VARIANTS));
+                    },
+                }
+            }
+        }
+
+        // part 0 is the block itself
+        let mut parts = Vec::new();
+        if !def.parts.is_empty() {
+            parts.push(textures.clone());
+        }
+        for (i, part) in def.parts.iter().enumerate() {
+            let field = format!("parts[{}]", i);
+            if part.offset == [0; 3] || parts.iter().any(|other| other.offset == part.offset) {
+                return Err(error(&file, Some(&format!("{}.offset", field)), format!("{:?} is already taken by another part", part.offset)));
+            }
+            parts.push(Part { offset: part.offset, ..face_textures(&file, &part.textures, &format!("{}.", field), txtr, &mut names)? });
+        }
+
+        // the blocks live for as long as the game does, so their strings are leaked into static ones
+        let mut properties: Vec<BlockTag> = Vec::with_capacity(def.properties.len());
+        for (i, property) in def.properties.into_iter().enumerate() {
+            let field = format!("properties[{}]", i);
+            let tag = match property {
+                PropertyDefinition::Nibble(name) => BlockTag::Nibble(leak(name)),
+                PropertyDefinition::Integer(name) => BlockTag::Integer(leak(name)),
+                PropertyDefinition::Float(name) => BlockTag::Float(leak(name)),
+                PropertyDefinition::String(name) => BlockTag::String(leak(name)),
+            };
+            if tag.name().is_empty() {
+                return Err(error(&file, Some(&field), String::from("the property name must not be empty")));
+            }
+            if properties.iter().any(|other| other.name() == tag.name()) {
+                return Err(error(&file, Some(&field), format!("property \"{}\" is declared twice", tag.name())));
+            }
+            properties.push(tag);
+        }
+
+        let toggle = match def.toggle.as_str() {
+            "" => None,
+            name => match properties.iter().find(|tag| tag.name() == name) {
+                Some(BlockTag::Nibble(name)) => Some(*name),
+                _ => return Err(error(&file, Some("toggle"), format!("\"{}\" is not a declared Nibble property", name))),
+            },
+        };
+
+        let mut state = BlockState::new().liquid(def.liquid).luminosity(def.light);
+        for tag in properties.iter() {
+            state = state.val(tag.name(), tag.default_value());
+        }
+
+        let layer = def.layer.unwrap_or(default_layer(def.mesh, def.transparent));
+        let id = leak(def.id);
+        sources.insert(id, file.clone());
+        blocks.insert(id, Block {
+            mesh: def.mesh,
+            shape: def.shape,
+            id,
+            name: leak(def.name),
+            texture: textures.texture,
+            texture_coord: textures.texture_coord,
+            tiles: textures.tiles,
+            connected: Box::leak(connected.into_boxed_slice()),
+            tint: def.tint,
+            state: state.build(),
+            properties: Box::leak(properties.into_boxed_slice()),
+            rotation: def.rotation,
+            parts: Box::leak(parts.into_boxed_slice()),
+            toggle,
+            transparent: def.transparent || def.mesh != MeshType::Cube || layer != Layer::Opaque,  // nothing is hidden behind plants, liquids or see-through blocks
+            layer,
+            falls: def.falls,
+            hardness: def.hardness,
+        });
+    }
+
+    Ok(blocks)
+}
+
+// liquids are blended, plants and transparent cubes are cut out, and the rest is opaque
+fn default_layer(mesh: MeshType, transparent: bool) -> Layer {
+    match mesh {
+        MeshType::Liquid => Layer::Translucent,
+        MeshType::Flora => Layer::Cutout,
+        MeshType::Cube if transparent => Layer::Cutout,
+        MeshType::Cube => Layer::Opaque,
+    }
+}
+
+// the textures of every face; prefix goes in front of the field names in the errors
+fn face_textures(file: &Path, textures: &FaceTextures, prefix: &str, txtr: &TextureAtlas, names: &mut HashMap<String, &'static str>) -> Result<Part, DefinitionError> {
+    let mut part = Part { offset: [0; 3], texture: [""; 6], texture_coord: Vec::with_capacity(6), tiles: [0; 6] };
+    for (i, &(field, name)) in textures.resolve().iter().enumerate() {
+        let field = format!("{}{}", prefix, field);
+        if name.is_empty() {
+            return Err(error(file, Some(&field), String::from("no texture given (nor in `side` or `all`)")));
+        }
+        if !txtr.contains(name) {
+            println!("Warning: {}", error(file, Some(&field), format!("unknown texture \"{}\"; using the missing texture", name)));
+        }
+        part.texture[i] = *names.entry(name.to_string()).or_insert_with(|| leak(name.to_string()));
+        part.texture_coord.push(txtr.texture_coord(name));
+        part.tiles[i] = txtr.tile(name);
+    }
+    Ok(part)
+}
+
+// reads a block definition; syntax and type errors are reported at their line and column
+fn parse(file: &Path, source: &str) -> Result<BlockDefinition, DefinitionError> {
+    ron::de::from_str(source).map_err(|e| DefinitionError {
+        file: file.to_path_buf(),
+        field: None,
+        // serde's own errors (e.g. a missing field or an unknown variant) have no position, but
+        // their message names what is wrong
+        position: Some((e.position.line, e.position.col)).filter(|&(line, _)| line != 0),
+        message: e.code.to_string(),
+    })
+}
+
+fn error(file: &Path, field: Option<&str>, message: String) -> DefinitionError {
+    DefinitionError {
+        file: file.to_path_buf(),
+        field: field.map(String::from),
+        position: None,
+        message,
+    }
+}
+
+fn leak(string: String) -> &'static str {
+    Box::leak(string.into_boxed_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the error in the definition
+    fn invalid(source: &str) -> DefinitionError {
+        match parse(Path::new("test.ron"), source) {
+            Ok(_) => panic!("the definition should be invalid"),
+            Err(e) => e,
+        }
+    }
+
+    const DOOR: &str = r#"(
+        id: "door",
+        name: "Door",  // a comment: with (brackets)
+        mesh: Cube,
+        textures: (all: "door_bottom"),
+        hardness: 0.6,
+        properties: [Nibble("open")],
+        parts: [
+            (offset: (0, 1, 0), textures: (all: "door_top")),
+        ],
+    )"#;
+
+    #[test]
+    fn valid_definitions_parse() {
+        assert!(parse(Path::new("test.ron"), DOOR).is_ok());
+    }
+
+    #[test]
+    fn syntax_and_type_errors_have_a_position() {
+        assert_eq!(invalid(&DOOR.replace("0.6", "\"hard\"")).position, Some((6, 19)));
+        assert_eq!(invalid(&DOOR.replace("(0, 1, 0)", "(0, true, 0)")).position, Some((9, 26)));
+        let e = invalid(&DOOR.replace("[Nibble(\"open\")]", "[Nibble(\"open\"),,]"));
+        assert_eq!(e.to_string(), "test.ron:7:37: Expected identifier");
+    }
+
+    #[test]
+    fn serde_errors_name_what_is_wrong() {
+        let e = invalid(&DOOR.replace("hardness: 0.6,", ""));
+        assert_eq!(e.message, "missing field `hardness`");
+        assert_eq!(e.to_string(), "test.ron: missing field `hardness`");
+        assert!(invalid(&DOOR.replace("hardness: 0.6,", "hardness: 0.6, weight: 3,")).message.starts_with("unknown field `weight`"));
+        assert!(invalid(&DOOR.replace("Cube", "Cubes")).message.starts_with("unknown variant `Cubes`"));
+    }
+}
@@ -12,6 +12,7 @@ use vulkano::memory::pool::StdMemoryPool;
 
 
 pub const CHUNK_RADIUS: u16 = 2;  // Player's chunk radius
+pub const REACH: f32 = 8.0;  // how far away (in blocks) the player can break blocks
 
 pub struct Camera<T> {
     rot_speed: f32,
@@ -47,11 +48,21 @@ impl<T> Camera<T> {
         self.rotation.y += Deg(y * self.rot_speed);
     }
 
-    pub fn chunk_pos(&self) -> [usize; 3] {
+    pub fn view(&self) -> Matrix4<f32> {
+        Matrix4::from_angle_x(self.rotation.x) * Matrix4::from_angle_y(self.rotation.y) *
+            Matrix4::look_at(Point3::new(self.position.x, self.position.y, -1.0+self.position.z), self.position, Vector3::new(0.0, -1.0, 0.0))
+    }
+
+    // the direction the camera is looking at in world space
+    pub fn direction(&self) -> Vector3<f32> {
+        self.view().invert().unwrap().transform_vector(Vector3::new(0.0, 0.0, -1.0))
+    }
+
+    pub fn chunk_pos(&self) -> [i32; 3] {
         [
-            (self.position.x/CHUNK_SIZE as f32).floor() as usize,
-            (self.position.y/CHUNK_SIZE as f32).floor() as usize,
-            (self.position.z/CHUNK_SIZE as f32).floor() as usize,
+            (self.position.x/CHUNK_SIZE as f32).floor() as i32,
+            (self.position.y/CHUNK_SIZE as f32).floor() as i32,
+            (self.position.z/CHUNK_SIZE as f32).floor() as i32,
         ]
     }
 }
@@ -62,8 +73,7 @@ impl Camera<vs::ty::Matrix> {
 
         // the closer the znear is to 0, the worse the depth buffering would perform
         let proj = perspective (Rad::from(self.fov), dimensions[0] as f32/dimensions[1] as f32, 0.1 , 1000.0);
-        let view = Matrix4::from_angle_x(self.rotation.x) * Matrix4::from_angle_y(self.rotation.y) *
-            Matrix4::look_at(Point3::new(self.position.x, self.position.y, -1.0+self.position.z), self.position, Vector3::new(0.0, -1.0, 0.0));
+        let view = self.view();
         let world = Matrix4::identity();
 
         self.mat_buf.next(
@@ -1,10 +1,13 @@
-use crate::mesh::{
-    mesh::Meshes
-};
-use crate::block::Block;
+use crate::block::{Block, BlockState};
+use crate::generator::climate::TintMap;
 
-use std::rc::Rc;
-use std::cell::RefCell;
+use serde::{Serialize, Deserialize};
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
 
 pub const CHUNK_SIZE: usize = 64;
@@ -17,7 +20,28 @@ pub struct Chunk {
     pub id: ChunkID,
     pub visible: bool,
     position: [u32; 3],  // position is relative towards to its parent sector; in chunks
-    block_data: Vec<Block>,
+    // shared with the workers meshing the chunk; a block change while they are at it copies the
+    // blocks instead of changing them under the workers
+    block_data: Arc<Vec<Block>>,
+    tints: Arc<TintMap>,  // biome tint colours on the block corners
+    pub modified: bool,  // differs from the generated terrain, so it has to be saved
+    pub revision: u32,  // counts the times the chunk was sent to be meshed; older meshes are dropped
+}
+
+// the loaded chunks around a chunk, to look up the blocks across its border while meshing
+// - keeps the blocks of the chunks as they were when it was made, so it can go to a worker
+pub struct Neighbours {
+    chunks: Vec<([u32; 3], Arc<Vec<Block>>)>,  // (position, block data)
+}
+
+// a saved chunk
+// - every different block (id and state) is in the palette once, and the blocks are stored as
+//   runs of the same palette entry in the same order as the block data
+#[derive(Serialize, Deserialize)]
+struct ChunkFile {
+    position: [u32; 3],
+    palette: Vec<(String, BlockState)>,
+    runs: Vec<(u32, u32)>,  // (palette index, length)
 }
 
 impl Chunk {
@@ -25,17 +49,31 @@ impl Chunk {
         // return a new chunk from sector
     }
 
-    pub fn new(id: ChunkID, position: [u32; 3], blocks: Vec<Block>) -> Self {
+    pub fn new(id: ChunkID, position: [u32; 3], blocks: Vec<Block>, tints: TintMap) -> Self {
         Self {
             id: id,
             position: position,
             visible: true,
-            block_data: blocks,
+            block_data: Arc::new(blocks),
+            tints: Arc::new(tints),
+            modified: false,
+            revision: 0,
         }
     }
 
-    pub fn render(&self, meshes: Rc<RefCell<Meshes>>) {
-        (*meshes).borrow_mut().onload_data(self.id, [self.position[0] as f32, self.position[1] as f32, self.position[2] as f32], &self.block_data);
+    // what a worker needs to mesh the chunk: its position, blocks and tints
+    pub fn mesh_data(&self) -> ([f32; 3], Arc<Vec<Block>>, Arc<TintMap>) {
+        ([self.position[0] as f32, self.position[1] as f32, self.position[2] as f32], self.block_data.clone(), self.tints.clone())
+    }
+
+    // the block at the position relative to the chunk origin
+    pub fn block(&self, pos: [usize; 3]) -> &Block {
+        &self.block_data[pos[0]*CHUNK_SIZE*CHUNK_SIZE+pos[1]*CHUNK_SIZE+pos[2]]
+    }
+
+    pub fn set_block(&mut self, pos: [usize; 3], block: Block) {
+        Arc::make_mut(&mut self.block_data)[pos[0]*CHUNK_SIZE*CHUNK_SIZE+pos[1]*CHUNK_SIZE+pos[2]] = block;
+        self.modified = true;
     }
 
     pub fn update(&mut self) {
@@ -45,11 +83,156 @@ impl Chunk {
 
     }
 
-    pub fn save(&self) {
+    // the file of the chunk in the save directory
+    pub fn file(dir: &Path, id: ChunkID) -> PathBuf {
+        dir.join(format!("chunk_{}_{}_{}.ron", id.0, id.1, id.2))
+    }
+
+    // saves the blocks (with their states and properties) into the save directory
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        let mut palette = Vec::new();
+        let mut indices: HashMap<(&str, &BlockState), u32> = HashMap::new();
+        let mut runs: Vec<(u32, u32)> = Vec::new();
+
+        for block in self.block_data.iter() {
+            let index = *indices.entry((block.id, &block.state)).or_insert_with(|| {
+                palette.push((block.id.to_string(), block.state.clone()));
+                palette.len() as u32-1
+            });
+
+            match runs.last_mut() {
+                Some((last, length)) if *last == index => *length += 1,
+                _ => runs.push((index, 1)),
+            }
+        }
+
+        let file = ChunkFile { position: self.position, palette, runs };
+        let data = ron::ser::to_string(&file).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        fs::write(Self::file(dir, self.id), data)
+    }
+
+    // loads a saved chunk; the block ids are looked up in blocks
+    pub fn load(dir: &Path, id: ChunkID, blocks: &HashMap<&'static str, Block>, tints: TintMap) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let data = fs::read_to_string(Self::file(dir, id))?;
+        let file: ChunkFile = ron::de::from_str(&data).map_err(|e| invalid(e.to_string()))?;
+
+        let palette = file.palette.into_iter().map(|(block_id, state)| {
+            let mut block = blocks.get(block_id.as_str()).ok_or_else(|| invalid(format!("unknown block \"{}\"", block_id)))?.clone();
+            // saved tags go over the defaults, so properties added to the definition since keep a value
+            let mut tags = block.state.tags.clone();
+            tags.extend(state.tags);
+            block.state = BlockState { tags, ..state };
+            // the parts of a structure have their own textures
+            if !block.parts.is_empty() {
+                block = block.part(block.state.part).ok_or_else(|| invalid(format!("block \"{}\" has no part {}", block_id, block.state.part)))?;
+            }
+            Ok(block)
+        }).collect::<io::Result<Vec<Block>>>()?;
+
+        let mut block_data = Vec::with_capacity(CHUNK_SIZE*CHUNK_SIZE*CHUNK_SIZE);
+        for (index, length) in file.runs {
+            let block = palette.get(index as usize).ok_or_else(|| invalid(format!("palette index {} out of range", index)))?;
+            for _ in 0..length {
+                block_data.push(block.clone());
+            }
+        }
+        if block_data.len() != CHUNK_SIZE*CHUNK_SIZE*CHUNK_SIZE {
+            return Err(invalid(format!("{} blocks instead of {}", block_data.len(), CHUNK_SIZE*CHUNK_SIZE*CHUNK_SIZE)));
+        }
+
+        let mut chunk = Self::new(id, file.position, block_data, tints);
+        chunk.modified = true;  // still differs from the generated terrain
+        Ok(chunk)
+    }
+}
+
+impl Neighbours {
+    // the chunks that share a face, an edge or a corner with the chunk; the ambient occlusion of
+    // the faces on the chunk's edges and corners looks into the diagonal ones
+    pub fn new(id: ChunkID, chunks: &[Chunk]) -> Self {
+        let distance = |a: u32, b: u32| (a as i64-b as i64).abs();
+        Self {
+            chunks: chunks.iter()
+                .filter(|c| c.id != id && distance(c.id.0, id.0) <= 1 && distance(c.id.1, id.1) <= 1 && distance(c.id.2, id.2) <= 1)
+                .map(|c| (c.position, c.block_data.clone()))
+                .collect(),
+        }
+    }
 
+    // the block at the world position; none when its chunk isn't loaded
+    pub fn block(&self, pos: [i64; 3]) -> Option<&Block> {
+        let size = CHUNK_SIZE as i64;
+        self.chunks.iter()
+            .find(|(position, _)| (0..3).all(|i| pos[i] >= position[i] as i64 && pos[i] < position[i] as i64+size))
+            .map(|(position, block_data)| &block_data[
+                (pos[0]-position[0] as i64) as usize*CHUNK_SIZE*CHUNK_SIZE
+                    +(pos[1]-position[1] as i64) as usize*CHUNK_SIZE
+                    +(pos[2]-position[2] as i64) as usize
+            ])
     }
+}
 
-    pub fn load(&mut self) {
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{BlockTag, TagValue, test_block};
+    use crate::generator::climate::Climate;
 
+    // a block whose properties start out with their default values, like the block definitions
+    fn block(id: &'static str, properties: &'static [BlockTag]) -> Block {
+        let mut block = Block { properties, ..test_block(id) };
+        for tag in properties {
+            block.state.tags.insert(tag.name().to_string(), tag.default_value());
+        }
+        block
     }
-}
\ No newline at end of file
+
+    // saves a chunk with a crop at the origin (the rest is air) and loads it with the given crop
+    fn round_trip(dir: &str, crop: Block, loaded: Block) -> Chunk {
+        // the process id keeps parallel and left over test runs apart
+        let dir = std::env::temp_dir().join(format!("{}_{}", dir, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let tints = || Climate::new(7).tint_map(&[0, 0, 0], CHUNK_SIZE);
+
+        let mut blocks = vec![block("air", &[]); CHUNK_SIZE*CHUNK_SIZE*CHUNK_SIZE];
+        blocks[0] = crop;
+        Chunk::new(ChunkID(0, 0, 0), [0, 0, 0], blocks, tints()).save(&dir).unwrap();
+
+        let mut definitions = HashMap::new();
+        definitions.insert("air", block("air", &[]));
+        definitions.insert("crop", loaded);
+        let chunk = Chunk::load(&dir, ChunkID(0, 0, 0), &definitions, tints()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        chunk
+    }
+
+    #[test]
+    fn typed_properties_survive_saving() {
+        const PROPERTIES: &[BlockTag] = &[
+            BlockTag::Nibble("growth"), BlockTag::Integer("age"), BlockTag::Float("moisture"), BlockTag::String("planter"),
+        ];
+        let mut crop = block("crop", PROPERTIES);
+        crop.state.tags.insert("growth".to_string(), TagValue::Nibble(7));
+        crop.state.tags.insert("age".to_string(), TagValue::Integer(-120));
+        crop.state.tags.insert("moisture".to_string(), TagValue::Float(0.25));
+        crop.state.tags.insert("planter".to_string(), TagValue::String("steve".to_string()));
+
+        let chunk = round_trip("mineblock_typed_properties", crop.clone(), block("crop", PROPERTIES));
+        assert_eq!(chunk.block([0, 0, 0]).state, crop.state);
+        assert_eq!(chunk.block([0, 0, 1]).id, "air");
+        assert!(chunk.modified);
+    }
+
+    #[test]
+    fn new_properties_get_their_defaults() {
+        let mut crop = block("crop", &[BlockTag::Nibble("growth")]);
+        crop.state.tags.insert("growth".to_string(), TagValue::Nibble(3));
+
+        let chunk = round_trip("mineblock_new_properties", crop, block("crop", &[BlockTag::Nibble("growth"), BlockTag::Integer("age")]));
+        let state = &chunk.block([0, 0, 0]).state;
+        assert_eq!(state.tag(&["growth"]), Some(&TagValue::Nibble(3)));
+        assert_eq!(state.tag(&["age"]), Some(&TagValue::Integer(0)));
+    }
+}
@@ -0,0 +1,106 @@
+use crate::block::Block;
+use crate::shape::Aabb;
+
+
+const GRAVITY: f32 = 32.0;  // in blocks/s²
+const TERMINAL_VELOCITY: f32 = 40.0;  // in blocks/s
+
+
+// Entities
+// - the things in the world that move on their own instead of being part of a chunk
+// - falling blocks fall under gravity and land as a block again
+// - items are blocks dropped on the ground (there is no inventory to pick them up into yet)
+// - entities only move down; the world decides what they fall through and what they land on
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum EntityKind {
+    FallingBlock,
+    Item,
+}
+
+#[derive(Clone, Debug)]
+pub struct Entity {
+    pub kind: EntityKind,
+    pub block: Block,  // the block that falls or got dropped
+    pub position: [f32; 3],  // the lower corner of the block the entity takes up
+    pub velocity: f32,  // downwards, in blocks/s
+    pub resting: bool,  // lying on the ground; only items rest
+}
+
+// what is below a falling entity
+pub enum Ground {
+    Passable,  // air, liquids and plants
+    Solid,  // anything else (and the world floor)
+    Unloaded,  // the chunk isn't loaded, so the entity waits for it
+}
+
+impl Entity {
+    pub fn falling_block(block: Block, pos: [i64; 3]) -> Self {
+        Self {
+            kind: EntityKind::FallingBlock,
+            block,
+            position: [pos[0] as f32, pos[1] as f32, pos[2] as f32],
+            velocity: 0.0,
+            resting: false,
+        }
+    }
+
+    pub fn item(block: Block, pos: [i64; 3]) -> Self {
+        Self {
+            kind: EntityKind::Item,
+            block,
+            position: [pos[0] as f32, pos[1] as f32, pos[2] as f32],
+            velocity: 0.0,
+            resting: false,
+        }
+    }
+
+    // the block position the entity is in
+    pub fn block_pos(&self) -> [i64; 3] {
+        [self.position[0].floor() as i64, self.position[1].floor() as i64, self.position[2].floor() as i64]
+    }
+
+    // the box the entity is meshed with, relative to its position; items are a small block
+    pub fn aabb(&self) -> Aabb {
+        match self.kind {
+            EntityKind::FallingBlock => Aabb::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]),
+            EntityKind::Item => Aabb::new([0.375, 0.0, 0.375], [0.625, 0.25, 0.625]),
+        }
+    }
+
+    // moves the entity down for dt seconds; returns the block position it landed in, if it did
+    // - ground gives what is at the block height below the entity's column
+    pub fn fall(&mut self, dt: f32, ground: impl Fn(i64) -> Ground) -> Option<[i64; 3]> {
+        let [x, y, z] = self.block_pos();
+        if self.resting {
+            match ground(y-1) {
+                Ground::Passable => self.resting = false,  // the ground went away
+                _ => return None,
+            }
+        }
+
+        self.velocity = (self.velocity+GRAVITY*dt).min(TERMINAL_VELOCITY);
+        let next = self.position[1]-self.velocity*dt;
+
+        // every block passed through on the way down, from the top
+        let top = self.position[1].ceil() as i64-1;
+        let mut below = top;
+        while below >= next.floor() as i64 {
+            match ground(below) {
+                Ground::Passable => below -= 1,
+                Ground::Solid => {
+                    self.position[1] = (below+1) as f32;
+                    self.velocity = 0.0;
+                    return Some([x, below+1, z]);
+                },
+                Ground::Unloaded => {
+                    self.velocity = 0.0;
+                    return None;
+                },
+            }
+        }
+
+        self.position[1] = next;
+        None
+    }
+}
@@ -0,0 +1,27 @@
+use noise::{NoiseFn, Fbm, Seedable, MultiFractal};
+
+
+pub const BEDROCK_DEPTH: u32 = 5;  // the thickest the bedrock layer gets
+
+
+// Bedrock
+// - the indestructible layer on top of the world floor
+// - always at least one block thick; the upper boundary is noisy so it doesn't look flat
+
+pub struct Bedrock {
+    noise: Fbm,
+}
+
+impl Bedrock {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            noise: Fbm::new().set_seed(seed).set_octaves(2).set_frequency(1.0/3.0),
+        }
+    }
+
+    // the number of bedrock blocks in the column at (x, z), counted from the world floor
+    pub fn height(&self, x: f64, z: f64) -> u32 {
+        let n = (self.noise.get([x, z])*1.5+1.0)/2.0;
+        1 + (n*(BEDROCK_DEPTH-1) as f64).round().max(0.0).min((BEDROCK_DEPTH-1) as f64) as u32
+    }
+}
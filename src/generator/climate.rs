@@ -0,0 +1,162 @@
+use noise::{NoiseFn, Fbm, Seedable, MultiFractal};
+
+use serde::Deserialize;
+
+
+// tint colours at the corners of the climate range: [cold-dry, cold-wet, hot-dry, hot-wet]
+// - cube.vert has a copy of them, since the cube vertices only keep the climate; these are the
+//   ones to change, and the tests check that the copy is the same
+const GRASS_COLORS: [[f32; 3]; 4] = [
+    [0.56, 0.69, 0.55],
+    [0.38, 0.62, 0.46],
+    [0.75, 0.72, 0.33],
+    [0.33, 0.78, 0.20],
+];
+const FOLIAGE_COLORS: [[f32; 3]; 4] = [
+    [0.45, 0.58, 0.45],
+    [0.28, 0.50, 0.36],
+    [0.62, 0.60, 0.20],
+    [0.22, 0.64, 0.10],
+];
+// the steps the temperature and the humidity of a block corner are rounded to; 5 bits each
+pub const CLIMATE_STEPS: u32 = 31;
+
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default, Deserialize)]
+pub enum Tint {
+    #[default]
+    None,
+    Grass,  // only the top face gets tinted
+    Foliage,  // every face gets tinted
+}
+
+
+// Climate
+// - temperature and humidity are both low frequency noise in [0, 1]
+// - the tint colour is a bilinear blend of the corner colours of the climate range; humidity is
+//   scaled down by temperature so cold biomes can never be as lush as hot ones
+
+pub struct Climate {
+    temperature: Fbm,
+    humidity: Fbm,
+}
+
+// the climate on the block corners of a chunk; (size+1)*(size+1) corners in x then z order
+// - the climate is kept in steps, so the cube vertices can carry it (see CubeVtx) and every mesh
+//   gets the same tint colours from it
+pub struct TintMap {
+    size: usize,
+    climate: Vec<[u32; 2]>,  // (temperature, humidity) in steps
+}
+
+impl Climate {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            temperature: Fbm::new().set_seed(seed).set_octaves(3).set_frequency(1.0/768.0),
+            humidity: Fbm::new().set_seed(seed.wrapping_add(1)).set_octaves(3).set_frequency(1.0/640.0),
+        }
+    }
+
+    pub fn temperature(&self, x: f64, z: f64) -> f64 {
+        ((self.temperature.get([x, z])+1.0)/2.0).clamp(0.0, 1.0)
+    }
+
+    pub fn humidity(&self, x: f64, z: f64) -> f64 {
+        ((self.humidity.get([x, z])+1.0)/2.0).clamp(0.0, 1.0)
+    }
+
+    // samples the climate of a chunk
+    // - each corner is the average of the four columns touching it, so neighbouring faces (and
+    //   chunks) always share the same colour on a shared corner
+    pub fn tint_map(&self, position: &[u32; 3], size: usize) -> TintMap {
+        // columns from one block before the chunk to one block after the chunk
+        let columns = size+2;
+        let mut column_climate = Vec::with_capacity(columns*columns);
+        for x in 0..columns {
+            for z in 0..columns {
+                // sampled at the centre of the column
+                let cx = position[0] as f64+x as f64-0.5;
+                let cz = position[2] as f64+z as f64-0.5;
+                let temperature = self.temperature(cx, cz);
+                column_climate.push([temperature, self.humidity(cx, cz)*temperature]);
+            }
+        }
+
+        let mut climate = Vec::with_capacity((size+1)*(size+1));
+        for x in 0..=size {
+            for z in 0..=size {
+                let mut corner = [0.0; 2];
+                for (cx, cz) in [(x, z), (x+1, z), (x, z+1), (x+1, z+1)].iter() {
+                    let column = column_climate[cx*columns+cz];
+                    for i in 0..2 {
+                        corner[i] += column[i]/4.0;
+                    }
+                }
+                climate.push([Self::step(corner[0]), Self::step(corner[1])]);
+            }
+        }
+
+        TintMap { size, climate }
+    }
+
+    fn step(value: f64) -> u32 {
+        (value.clamp(0.0, 1.0)*CLIMATE_STEPS as f64).round() as u32
+    }
+
+    // the tint colour of the climate in steps (see cube.vert for the same in the shader)
+    fn blend(colors: &[[f32; 3]; 4], climate: [u32; 2]) -> [f32; 3] {
+        let (t, h) = (climate[0] as f32/CLIMATE_STEPS as f32, climate[1] as f32/CLIMATE_STEPS as f32);
+        let mut color = [0.0; 3];
+        for i in 0..3 {
+            let cold = colors[0][i]*(1.0-h) + colors[1][i]*h;
+            let hot = colors[2][i]*(1.0-h) + colors[3][i]*h;
+            color[i] = cold*(1.0-t) + hot*t;
+        }
+        color
+    }
+}
+
+impl TintMap {
+    // the colour on the corner at (x, z) relative to the chunk origin; within [0, size]
+    pub fn corner(&self, tint: Tint, x: usize, z: usize) -> [f32; 3] {
+        let climate = self.climate[x*(self.size+1)+z];
+        match tint {
+            Tint::None => [1.0, 1.0, 1.0],
+            Tint::Grass => Climate::blend(&GRASS_COLORS, climate),
+            Tint::Foliage => Climate::blend(&FOLIAGE_COLORS, climate),
+        }
+    }
+
+    // the climate on the corner at (x, z), packed for the cube vertices: the temperature in the low
+    // 5 bits and the humidity in the high 5 bits
+    pub fn climate(&self, x: usize, z: usize) -> u32 {
+        let climate = self.climate[x*(self.size+1)+z];
+        climate[0] | climate[1] << 5
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the numbers in the declaration of the constant in the shader
+    fn shader_constant(name: &str) -> Vec<f32> {
+        let shader = include_str!("../../resource/shaders/cube.vert");
+        let line = shader.lines().find(|line| line.starts_with("const") && line.contains(&format!(" {}", name)))
+            .unwrap_or_else(|| panic!("cube.vert has no {}", name));
+        let value = line.split('=').nth(1).unwrap();
+        value.split(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .filter(|number| !number.is_empty() && *number != "3")  // the 3 of vec3
+            .map(|number| number.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn the_shader_has_the_same_tints() {
+        for (name, colors) in [("GRASS_COLORS", GRASS_COLORS), ("FOLIAGE_COLORS", FOLIAGE_COLORS)].iter() {
+            let expected: Vec<f32> = colors.iter().flatten().cloned().collect();
+            assert_eq!(shader_constant(name), expected, "{} in cube.vert", name);
+        }
+        assert_eq!(shader_constant("CLIMATE_STEPS"), vec![CLIMATE_STEPS as f32]);
+    }
+}
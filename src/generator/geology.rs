@@ -0,0 +1,122 @@
+use noise::{NoiseFn, Fbm, Worley, Seedable, MultiFractal};
+
+
+const REGION_SIZE: f64 = 512.0;  // rough width of a geological region in blocks
+const BAND_THICKNESS: f64 = 6.0;  // thickness of a single rock band
+const MAX_TILT: f64 = 0.35;  // steepest slope of the strata (rise over run)
+const PERTURBATION: f64 = 4.0;  // how far (in blocks) the bands get pushed up and down
+const BASEMENT: f64 = 16.0;  // below this the rock is always granite
+
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum RockType {
+    Stone,
+    Granite,
+    Sandstone,
+    Limestone,
+    Slate,
+}
+
+impl RockType {
+    // the id of the block in the terrain palette
+    pub fn block_id(&self) -> &'static str {
+        match self {
+            RockType::Stone => "stone",
+            RockType::Granite => "granite",
+            RockType::Sandstone => "sandstone",
+            RockType::Limestone => "limestone",
+            RockType::Slate => "slate",
+        }
+    }
+}
+
+// the strata of each kind of region, from bottom to top (repeats upwards)
+const COLUMNS: &[&[RockType]] = &[
+    &[RockType::Granite, RockType::Granite, RockType::Stone],  // granite massif
+    &[RockType::Slate, RockType::Stone, RockType::Slate, RockType::Granite],  // metamorphic belt
+    &[RockType::Limestone, RockType::Sandstone, RockType::Slate, RockType::Sandstone],  // sedimentary basin
+    &[RockType::Sandstone, RockType::Limestone, RockType::Limestone, RockType::Stone],  // carbonate platform
+];
+
+
+// Geology
+// - splits the world into regions (worley cells), each with its own kind of strata and tilt
+//     - the tilt changes abruptly at the region borders, which ends up looking like faults
+// - the rock bands are perturbed with noise so they are wavy instead of perfectly planar
+// - the rock of the strata at a height is also the host rock ores can form in (see `Strata::rock`)
+
+pub struct Geology {
+    regions: Worley,
+    region_warp: Fbm,  // makes the region borders irregular
+    perturb: Fbm,
+}
+
+// the strata of a single column of blocks
+#[derive(Copy, Clone)]
+pub struct Strata {
+    column: &'static [RockType],
+    offset: f64,  // vertical shift of the bands in this column
+}
+
+impl Geology {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            regions: Worley::new().set_seed(seed).set_frequency(1.0/REGION_SIZE),
+            region_warp: Fbm::new().set_seed(seed.wrapping_add(1)).set_octaves(3).set_frequency(1.0/128.0),
+            perturb: Fbm::new().set_seed(seed.wrapping_add(2)).set_octaves(3).set_frequency(1.0/48.0),
+        }
+    }
+
+    // the strata of the column at (x, z); reuse it for every block in the column
+    pub fn strata(&self, x: f64, z: f64) -> Strata {
+        let warp = self.region_warp.get([x, z])*REGION_SIZE/8.0;
+
+        // worley noise without the range enabled gives a constant value for every cell in [-1, 1]
+        let region = (self.regions.get([x+warp, z-warp])+1.0)/2.0;
+        let column = COLUMNS[((region*COLUMNS.len() as f64) as usize).min(COLUMNS.len()-1)];
+
+        let tilt_x = (region*37.0).sin()*MAX_TILT;
+        let tilt_z = (region*53.0).cos()*MAX_TILT;
+
+        Strata {
+            column,
+            offset: tilt_x*x + tilt_z*z + self.perturb.get([x, z])*PERTURBATION,
+        }
+    }
+}
+
+impl Strata {
+    // the rock at height y of the column
+    pub fn rock(&self, y: f64) -> RockType {
+        if y < BASEMENT {
+            return RockType::Granite;
+        }
+
+        let band = ((y+self.offset)/BAND_THICKNESS).floor() as i64;
+        self.column[band.rem_euclid(self.column.len() as i64) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strata_are_deterministic_bands() {
+        let (geology, again) = (Geology::new(3), Geology::new(3));
+        for i in 0..64 {
+            let (x, z) = ((i%8) as f64*200.0, (i/8) as f64*200.0);
+            let (strata, same) = (geology.strata(x, z), again.strata(x, z));
+            for y in 0..256 {
+                assert_eq!(strata.rock(y as f64), same.rock(y as f64));
+            }
+
+            // granite below the basement, the rocks of the region's column above it
+            assert!((0..BASEMENT as u32).all(|y| strata.rock(y as f64) == RockType::Granite));
+            assert!((BASEMENT as u32..256).all(|y| strata.column.contains(&strata.rock(y as f64))));
+            // and the rock only changes from one band to the next
+            let changes = (BASEMENT as u32..256).filter(|&y| strata.rock(y as f64) != strata.rock(y as f64+1.0)).count();
+            assert!(changes as f64 <= (256.0-BASEMENT)/BAND_THICKNESS+1.0);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use crate::block::Block;
+use crate::generator::TerrainGenerator;
+use crate::generator::geology::Geology;
+
+use noise::{NoiseFn, Fbm, Seedable, MultiFractal};
+
+use std::collections::HashMap;
+
+
+const ISLAND_LAYER: f64 = 224.0;  // the altitude where the islands are the widest
+const ISLAND_TOP: f64 = 40.0;  // how far above the layer the island tops can rise
+const ISLAND_BOTTOM: f64 = 112.0;  // how far below the layer the undersides can hang
+const DIRT_DEPTH: usize = 3;
+
+
+// Sky Island Generator
+// - floating landmasses made from 3D noise, with empty void between them
+// - a vertical falloff around ISLAND_LAYER keeps the islands in a band of the sky
+//     - above the layer the falloff is steep, which flattens the tops
+//     - below the layer the falloff is gentle but scaled by the island mask, so only the centre of
+//       an island hangs deep and the stone underside tapers off towards its edges
+
+pub struct SkyIsland {
+    density: Fbm,  // 3D noise for the island shape
+    mask: Fbm,  // 2D noise on where the islands are
+    geology: Geology,
+}
+
+impl SkyIsland {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            density: Fbm::new().set_seed(seed).set_octaves(4).set_frequency(1.0/96.0),
+            mask: Fbm::new().set_seed(seed.wrapping_add(1)).set_octaves(3).set_frequency(1.0/256.0),
+            geology: Geology::new(seed.wrapping_add(2)),
+        }
+    }
+
+    // whether there is land at the given position
+    pub fn solid(&self, x: f64, y: f64, z: f64) -> bool {
+        if !(ISLAND_LAYER-ISLAND_BOTTOM..=ISLAND_LAYER+ISLAND_TOP).contains(&y) {
+            return false;
+        }
+
+        let mask = self.mask.get([x, z])*2.0-0.1;
+        if mask <= 0.0 {
+            return false;
+        }
+
+        let dy = y-ISLAND_LAYER;
+        let falloff = if dy >= 0.0 {
+            (dy/ISLAND_TOP)*(dy/ISLAND_TOP)
+        } else {
+            -dy/ISLAND_BOTTOM
+        };
+
+        self.density.get([x, y, z])*0.5 + mask - falloff > 0.0
+    }
+}
+
+impl TerrainGenerator for SkyIsland {
+    fn generate(&self, blocks: &HashMap<&'static str, Block>, position: &[u32; 3], size: usize) -> Vec<Block> {
+        // each column also samples a few blocks above the chunk to know how deep the dirt goes
+        let column = size+DIRT_DEPTH;
+        let mut solid = Vec::with_capacity(size*size*column);
+        for x in 0..size {
+            for z in 0..size {
+                for y in 0..column {
+                    solid.push(self.solid(
+                        (position[0] as usize+x) as f64,
+                        (position[1] as usize+y) as f64,
+                        (position[2] as usize+z) as f64,
+                    ));
+                }
+            }
+        }
+        let solid = |x: usize, y: usize, z: usize| solid[(x*size+z)*column+y];
+
+        let mut strata = Vec::with_capacity(size*size);
+        for x in 0..size {
+            for z in 0..size {
+                strata.push(self.geology.strata((position[0] as usize+x) as f64, (position[2] as usize+z) as f64));
+            }
+        }
+
+        let mut block_data: Vec<Block> = Vec::with_capacity(size*size*size);
+
+        for x in 0..size {
+            for y in 0..size {
+                for z in 0..size {
+                    if !solid(x, y, z) {
+                        block_data.push(blocks["air"].clone());
+                    } else if !solid(x, y+1, z) {
+                        block_data.push(blocks["grass"].clone());
+                    } else if (y+2..=y+DIRT_DEPTH).any(|y| !solid(x, y, z)) {
+                        block_data.push(blocks["dirt"].clone());
+                    } else {
+                        let rock = strata[x*size+z].rock((position[1] as usize+y) as f64);
+                        block_data.push(blocks[rock.block_id()].clone());
+                    }
+                }
+            }
+        }
+
+        block_data
+    }
+}
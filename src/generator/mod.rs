@@ -0,0 +1,19 @@
+use crate::block::Block;
+
+use std::collections::HashMap;
+
+pub mod mountain;
+pub mod island;
+pub mod geology;
+pub mod climate;
+pub mod bedrock;
+pub mod plants;
+
+
+// A terrain generator fills in the block data of a single chunk
+// - block data is laid out the same way as the chunk stores it (x, then y, then z)
+// - `blocks` is the block palette from the Terrain struct
+// - the workers generate chunks at the same time, so generators can't change while generating
+pub trait TerrainGenerator: Send + Sync {
+    fn generate(&self, blocks: &HashMap<&'static str, Block>, position: &[u32; 3], size: usize) -> Vec<Block>;
+}
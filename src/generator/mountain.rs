@@ -0,0 +1,192 @@
+use crate::block::Block;
+use crate::generator::TerrainGenerator;
+use crate::generator::geology::Geology;
+use crate::world::WORLD_HEIGHT;
+
+use noise::{NoiseFn, Fbm, RidgedMulti, Seedable, MultiFractal};
+
+use std::collections::HashMap;
+
+
+const BASE_HEIGHT: f64 = 96.0;  // height of the lowlands
+const HILL_HEIGHT: f64 = 12.0;  // amplitude of the rolling hills on the lowlands
+const PEAK_HEIGHT: f64 = (WORLD_HEIGHT-16) as f64;  // leaves a bit of air above the highest peaks
+
+const WARP_STRENGTH: f64 = 96.0;  // how far (in blocks) the coordinates get pushed around
+const STEEP_SLOPE: u32 = 2;  // height difference to a neighbouring column where grass no longer holds
+const DIRT_DEPTH: u32 = 4;
+
+
+// Mountain Generator
+// - the lowlands are gentle hills, the highlands are ridges made from ridged multifractal noise
+// - the ridges are sampled on domain warped coordinates so the mountain ranges bend instead
+//   of following the noise grid
+// - steep slopes are exposed rock instead of grass
+
+pub struct Mountain {
+    hills: Fbm,
+    highland: Fbm,  // mask on where the mountain ranges are
+    ridges: RidgedMulti,
+    warp_x: Fbm,
+    warp_z: Fbm,
+    geology: Geology,
+}
+
+impl Mountain {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            hills: Fbm::new().set_seed(seed).set_octaves(4).set_frequency(1.0/256.0),
+            highland: Fbm::new().set_seed(seed.wrapping_add(1)).set_octaves(3).set_frequency(1.0/1024.0),
+            ridges: RidgedMulti::new().set_seed(seed.wrapping_add(2)).set_octaves(5).set_frequency(1.0/512.0),
+            warp_x: Fbm::new().set_seed(seed.wrapping_add(3)).set_octaves(3).set_frequency(1.0/384.0),
+            warp_z: Fbm::new().set_seed(seed.wrapping_add(4)).set_octaves(3).set_frequency(1.0/384.0),
+            geology: Geology::new(seed.wrapping_add(5)),
+        }
+    }
+
+    // the terrain height of the column at (x, z)
+    pub fn height(&self, x: f64, z: f64) -> u32 {
+        let hills = self.hills.get([x, z])*HILL_HEIGHT;
+
+        // the highland mask goes smoothly from 0 (lowlands) to 1 (mountain ranges)
+        let mask = ((self.highland.get([x, z])+1.0)/2.0 - 0.35)/0.4;
+        let mask = mask.clamp(0.0, 1.0);
+        let mask = mask*mask*(3.0-2.0*mask);
+
+        // domain warping
+        let wx = x + self.warp_x.get([x, z])*WARP_STRENGTH;
+        let wz = z + self.warp_z.get([x, z])*WARP_STRENGTH;
+
+        // ridged multifractal is roughly within [-1, 1]; sharpen it so the peaks stand out
+        let ridge = ((self.ridges.get([wx, wz])+1.0)/2.0).clamp(0.0, 1.0).powf(1.5);
+
+        let height = BASE_HEIGHT + hills + mask*ridge*(PEAK_HEIGHT-BASE_HEIGHT);
+        height.max(1.0).min((WORLD_HEIGHT-1) as f64) as u32
+    }
+}
+
+impl TerrainGenerator for Mountain {
+    fn generate(&self, blocks: &HashMap<&'static str, Block>, position: &[u32; 3], size: usize) -> Vec<Block> {
+        // height map with a one block border so the slope can be found at the chunk edges
+        let border = size+2;
+        let mut heights = Vec::with_capacity(border*border);
+        for x in 0..border {
+            for z in 0..border {
+                heights.push(self.height(
+                    position[0] as f64+x as f64-1.0,
+                    position[2] as f64+z as f64-1.0,
+                ));
+            }
+        }
+        let height = |x: usize, z: usize| heights[(x+1)*border+(z+1)];
+
+        // the rock strata and whether the slope is too steep for grass, of every column
+        let mut strata = Vec::with_capacity(size*size);
+        let mut steep = Vec::with_capacity(size*size);
+        for x in 0..size {
+            for z in 0..size {
+                strata.push(self.geology.strata((position[0] as usize+x) as f64, (position[2] as usize+z) as f64));
+
+                // steepest drop towards any of the 4 neighbouring columns
+                let ground = height(x, z);
+                let slope = [
+                    heights[x*border+(z+1)],
+                    heights[(x+2)*border+(z+1)],
+                    heights[(x+1)*border+z],
+                    heights[(x+1)*border+(z+2)],
+                ].iter().map(|&h| ground.abs_diff(h)).max().unwrap();
+                steep.push(slope > STEEP_SLOPE);
+            }
+        }
+
+        let mut block_data: Vec<Block> = Vec::with_capacity(size*size*size);
+
+        for x in 0..size {
+            for y in position[1]..position[1]+size as u32 {
+                for z in 0..size {
+                    let ground = height(x, z);
+                    let rock = strata[x*size+z].rock(y as f64).block_id();
+
+                    if y > ground {
+                        block_data.push(blocks["air"].clone());
+                    } else if steep[x*size+z] {
+                        block_data.push(blocks[rock].clone());
+                    } else if y == ground {
+                        block_data.push(blocks["grass"].clone());
+                    } else if y+DIRT_DEPTH > ground {
+                        block_data.push(blocks["dirt"].clone());
+                    } else {
+                        block_data.push(blocks[rock].clone());
+                    }
+                }
+            }
+        }
+
+        block_data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::test_block;
+
+    const ROCKS: [&str; 5] = ["stone", "granite", "sandstone", "limestone", "slate"];
+
+    // the heights of a 64x64 grid of columns, 128 blocks apart
+    fn heights(mountain: &Mountain) -> Vec<u32> {
+        (0..64*64).map(|i| mountain.height((i/64) as f64*128.0, (i%64) as f64*128.0)).collect()
+    }
+
+    #[test]
+    fn heights_are_deterministic_and_reach_the_peaks() {
+        let grid = heights(&Mountain::new(7));
+        assert_eq!(grid, heights(&Mountain::new(7)));
+        assert_ne!(grid, heights(&Mountain::new(8)));
+
+        // both lowlands and mountain ranges, and nothing outside of the world
+        let lowest = *grid.iter().min().unwrap() as f64;
+        let highest = *grid.iter().max().unwrap() as f64;
+        assert!((BASE_HEIGHT-2.0*HILL_HEIGHT..BASE_HEIGHT+HILL_HEIGHT).contains(&lowest), "lowest {}", lowest);
+        assert!(highest > (BASE_HEIGHT+PEAK_HEIGHT)/2.0 && highest < WORLD_HEIGHT as f64, "highest {}", highest);
+    }
+
+    #[test]
+    fn steep_slopes_are_bare_rock() {
+        let mountain = Mountain::new(7);
+        let mut blocks = HashMap::new();
+        for id in ["air", "grass", "dirt"].iter().chain(ROCKS.iter()) {
+            blocks.insert(*id, test_block(id));
+        }
+
+        // a column of chunks on the slopes of the highest peak of the seed
+        let (size, x0, z0) = (32, 3440, 4976);
+        let chunks = (0..WORLD_HEIGHT/size as u32)
+            .map(|y| mountain.generate(&blocks, &[x0, y*size as u32, z0], size))
+            .collect::<Vec<_>>();
+        let block = |x: usize, y: u32, z: usize| chunks[(y/size as u32) as usize][x*size*size+(y%size as u32) as usize*size+z].id;
+
+        let (mut steep, mut gentle) = (0, 0);
+        for x in 0..size {
+            for z in 0..size {
+                let (wx, wz) = ((x0 as usize+x) as f64, (z0 as usize+z) as f64);
+                let ground = mountain.height(wx, wz);
+                let slope = [(-1.0, 0.0), (1.0, 0.0), (0.0, -1.0), (0.0, 1.0)].iter()
+                    .map(|(dx, dz)| (mountain.height(wx+dx, wz+dz) as i64-ground as i64).abs())
+                    .max().unwrap();
+
+                assert_eq!(block(x, ground+1, z), "air");
+                if slope > STEEP_SLOPE as i64 {
+                    steep += 1;
+                    assert!(ROCKS.contains(&block(x, ground, z)), "{} on a slope of {}", block(x, ground, z), slope);
+                    assert!(ROCKS.contains(&block(x, ground-1, z)));
+                } else {
+                    gentle += 1;
+                    assert_eq!(block(x, ground, z), "grass");
+                    assert_eq!(block(x, ground-1, z), "dirt");
+                }
+            }
+        }
+        assert!(steep > 0 && gentle > 0, "{} steep and {} gentle columns", steep, gentle);
+    }
+}
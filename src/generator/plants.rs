@@ -0,0 +1,53 @@
+use noise::{NoiseFn, Fbm, Seedable, MultiFractal};
+
+
+const TALL_GRASS: f64 = 0.35;  // the chance of tall grass in the middle of a patch
+const FLOWERS: f64 = 0.02;
+const SAPLINGS: f64 = 0.004;
+
+
+// Plants
+// - the tall grass, flowers and saplings on top of the grass blocks
+// - tall grass grows in patches; flowers and saplings are scattered evenly
+// - every column decides on its own from the seed, so the plants are the same however the
+//   chunks are generated
+
+pub struct Plants {
+    patches: Fbm,
+    seed: u32,
+}
+
+impl Plants {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            patches: Fbm::new().set_seed(seed).set_octaves(2).set_frequency(1.0/24.0),
+            seed,
+        }
+    }
+
+    // the id of the plant on the grass block in the column at (x, z), if there is one
+    pub fn plant(&self, x: i64, z: i64) -> Option<&'static str> {
+        let density = ((self.patches.get([x as f64, z as f64])+1.0)/2.0).clamp(0.0, 1.0);
+        let roll = self.roll(x, z);
+        if roll < SAPLINGS {
+            Some("sapling")
+        } else if roll < SAPLINGS+FLOWERS {
+            Some("dandelion")
+        } else if roll < SAPLINGS+FLOWERS+density*density*TALL_GRASS {
+            Some("tall_grass")
+        } else {
+            None
+        }
+    }
+
+    // a number within [0, 1) that only depends on the column and the seed
+    fn roll(&self, x: i64, z: i64) -> f64 {
+        let mut h = (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+            ^ (z as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+            ^ (self.seed as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+        h ^= h >> 33;
+        h = h.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+        h ^= h >> 33;
+        (h >> 11) as f64/(1u64 << 53) as f64
+    }
+}
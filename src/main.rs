@@ -13,11 +13,19 @@ use cgmath::{Deg, Rad, Euler, Angle};
 
 
 use crate::renderer::Render;
+use crate::camera::REACH;
+use crate::world::WORLD_FLOOR;
+use crate::mesh::cube::Mesher;
+use crate::terrain::WorldType;
 use crate::ui::Widget;
 use crate::ui::text::Text;
 use std::rc::Rc;
 use std::borrow::BorrowMut;
 use std::time::Instant;
+use std::env;
+
+// the blocks that can be placed, selected with the number keys
+const HOTBAR: [&str; 9] = ["glass", "stone", "log", "sand", "door", "bed", "stained_glass", "water", "lava"];
 
 mod mesh;
 mod ui;
@@ -28,14 +36,38 @@ mod texture;
 mod chunk;
 mod world;
 mod block;
+mod blockdef;
+mod orientation;
+mod shape;
+mod entity;
+mod worker;
 mod camera;
 mod terrain;
+mod generator;
 mod datatypes;
 
 
+// the world type of a new world, from `--world-type <mountains|sky_islands>`; a saved world keeps
+// the type it was made with
+fn world_type() -> WorldType {
+    let args: Vec<String> = env::args().collect();
+    match args.iter().position(|arg| arg == "--world-type").map(|i| args.get(i+1)) {
+        None => WorldType::Mountains,
+        Some(Some(name)) => WorldType::from_name(name).unwrap_or_else(|| {
+            println!("Warning: unknown world type \"{}\" (mountains or sky_islands); using mountains", name);
+            WorldType::Mountains
+        }),
+        Some(None) => {
+            println!("Warning: --world-type needs a world type (mountains or sky_islands); using mountains");
+            WorldType::Mountains
+        },
+    }
+}
+
 fn main() {
     println!("PROGRAM - BEGIN INITIALIZATION");
     let mut maximized = false;
+    let world_type = world_type();
 
     // setup
     let instance= {
@@ -63,16 +95,18 @@ fn main() {
     println!("PROGRAM - BEGIN MAIN PROGRAM");
 
     use winit::event_loop::ControlFlow;
-    use winit::event::{Event, WindowEvent, DeviceEvent, VirtualKeyCode as K, KeyboardInput, ElementState};
+    use winit::event::{Event, WindowEvent, DeviceEvent, VirtualKeyCode as K, KeyboardInput, ElementState, MouseButton};
     use winit::dpi::Position;
 
     let mut rotation = Euler::new(Deg(0.0 as f32), Deg(0.0), Deg(0.0));  // the rotation of the player's camera in Radian
     let mut pressed: Vec<K> = Vec::new();  // keyboard pressed for player translation
+    let mut held = 0;  // the selected block in the hotbar
+    let mut mesher = Mesher::Greedy;
     let mut cmd_mode = false;  // command/chat mode to use commands/chat/or simply exit the mouse lock state TODO: temporary; we'll be using a special struct to handle states and inputs
     // TODO: a global pushdown state-machine stack to create an pause menu
     // TODO: also use cgmath's position and etc. for actual position to stay consistent
 
-    let mut render = Render::new(physical.clone(), device.clone(),queue.clone(), surface.clone());
+    let mut render = Render::new(physical.clone(), device.clone(),queue.clone(), surface.clone(), world_type);
 
     let txt = render.ui.add_widget(Text::new("FPS: 0".into(), [-0.9, -0.9], 0.01));
     println!("PROGRAM - START MAIN LOOP");
@@ -108,6 +142,15 @@ fn main() {
                                         surface.window().set_maximized(maximized);
                                     },
                                     K::T => {cmd_mode = !cmd_mode},
+                                    K::M => {
+                                        mesher = if mesher == Mesher::Greedy {Mesher::Naive} else {Mesher::Greedy};
+                                        println!("Mesher: {:?}", mesher);
+                                        render.world.set_mesher(mesher);
+                                    },
+                                    K::Key1 | K::Key2 | K::Key3 | K::Key4 | K::Key5 | K::Key6 | K::Key7 | K::Key8 | K::Key9 => {
+                                        held = key.unwrap() as usize-K::Key1 as usize;
+                                        println!("Holding: {}", render.world.block_name(HOTBAR[held]));
+                                    },
                                     K::A => { if !pressed.contains(&K::A) {pressed.push(K::A);} },
                                     K::D => { if !pressed.contains(&K::D) {pressed.push(K::D);} },
                                     K::W => { if !pressed.contains(&K::W) {pressed.push(K::W);} },
@@ -130,6 +173,22 @@ fn main() {
                             }
                         }
                     },
+                    WindowEvent::MouseInput { state: ElementState::Pressed, button: MouseButton::Left, .. } if !cmd_mode => {
+                        if let Some(hit) = render.world.raycast(render.cam.position, render.cam.direction(), REACH) {
+                            render.world.break_block(hit.block);
+                        }
+                    },
+                    WindowEvent::MouseInput { state: ElementState::Pressed, button: MouseButton::Right, .. } if !cmd_mode => {
+                        let look = render.cam.direction();
+                        if let Some(hit) = render.world.raycast(render.cam.position, look, REACH) {
+                            // blocks that can be used (doors) get used, the rest get a block placed
+                            // against the face that was hit
+                            let pos = [hit.block[0]+hit.normal[0], hit.block[1]+hit.normal[1], hit.block[2]+hit.normal[2]];
+                            if !render.world.use_block(hit.block) && hit.normal != [0; 3] {
+                                render.world.place_block(pos, HOTBAR[held], look);
+                            }
+                        }
+                    },
                     _ => {}
                 }
             },
@@ -147,6 +206,11 @@ fn main() {
             },
             // this calls last after all the event finishes emitting
             // and only calls once, which is great for updating mutable variables since it'll be uniform
+            Event::LoopDestroyed => {
+                if let Err(e) = render.world.save() {
+                    println!("Failed to save the world: {}", e);
+                }
+            },
             Event::MainEventsCleared => {
                 if pressed.contains(&K::A) {render.cam.translate(-Rad(rotation.y).0.cos(), 0.0, Rad(rotation.y).0.sin())}
                 if pressed.contains(&K::D) {render.cam.translate(Rad(rotation.y).0.cos(), 0.0, -Rad(rotation.y).0.sin())}
@@ -155,6 +219,9 @@ fn main() {
                 if pressed.contains(&K::LShift) {render.cam.translate(0.0, -1.0, 0.0)}
                 if pressed.contains(&K::Space)  {render.cam.translate(0.0, 1.0, 0.0)}
 
+                // the world floor is solid
+                if render.cam.position.y < WORLD_FLOOR as f32 {render.cam.position.y = WORLD_FLOOR as f32}
+
             },
             Event::RedrawEventsCleared => {
                 render.update(device.clone(), queue.clone(), dimensions);
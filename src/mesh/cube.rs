@@ -1,37 +1,47 @@
 use crate::renderer::CubeVtx;
-use crate::texture::TextureAtlas;
-use crate::chunk::{CHUNK_SIZE, ChunkID};
-use crate::chunk::Chunk;
+use crate::texture::{TextureAtlas, Tile, CONNECTED_VARIANTS};
+use crate::chunk::{CHUNK_SIZE, Neighbours};
 use crate::block::Block;
+use crate::entity::Entity;
+use crate::generator::climate::{Tint, TintMap};
+use crate::orientation::{NORMALS, U, V};
+use crate::shape::{CONNECTIONS, Aabb, Shape};
 use crate::mesh::mesh::{
+    ChunkBuffers,
+    Layer,
+    LAYERS,
     Mesh,
-    MeshType
+    MeshBuffers,
+    MeshType,
 };
 
 
+use serde::{Serialize, Deserialize};
+
 use vulkano::device::Device;
 use vulkano::image::{SwapchainImage};
 use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract, viewport::Viewport};
+use vulkano::pipeline::depth_stencil::DepthStencil;
 use vulkano::framebuffer::{Subpass, RenderPassAbstract};
 use vulkano::descriptor::{descriptor_set::PersistentDescriptorSet, DescriptorSet};
 use vulkano::sampler::{Sampler, Filter, MipmapMode, SamplerAddressMode};
 use vulkano::memory::pool::MemoryPool;
 use winit::window::Window;
-use vulkano::buffer::cpu_pool::CpuBufferPoolSubbuffer;
+use vulkano::buffer::{CpuAccessibleBuffer, cpu_pool::CpuBufferPoolSubbuffer};
 
-use std::rc::Rc;
 use std::sync::Arc;
 use std::iter;
+use std::collections::HashMap;
 use std::thread::sleep;
 use std::time::Duration;
 use std::ops::{Sub, Range};
 
 
-const CUBE_FACES: u32 = 6;
 const VERT_PER_FACE: u32 = 4;
-const VERT_PER_CUBE: u32 = CUBE_FACES*VERT_PER_FACE;
-const IND_PER_FACE: u32 = 6;
-const IND_PER_CUBE: u32 = CUBE_FACES*IND_PER_FACE;
+const OPEN: u8 = 3;  // the ambient occlusion of a corner with nothing around it
+// the corners of a face (top left, top right, bottom right, bottom left) along the u and v of the face
+pub const QUAD_CORNERS: [(usize, usize); 4] = [(0, 0), (1, 0), (1, 1), (0, 1)];
+pub const ENTITY_CLIMATE: u32 = 20 | 8 << 5;  // entities aren't in a chunk, so they get a plain temperate climate (see TintMap::climate)
 
 
 #[derive(Eq, PartialEq)]
@@ -49,47 +59,364 @@ pub enum Bound {
     LBound, // lower bound
 }
 
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
 pub enum Axis {
     X,
     Y,
     Z
 }
 
+// how the faces of the cube blocks are turned into quads
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Mesher {
+    Naive,  // a quad for every visible face
+    Greedy,  // the full faces next to each other are merged into larger quads
+}
+
+// how a face looks
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+struct FaceLook {
+    tile: u32,  // after the connected variant
+    turn: u8,  // the rotation of the texture (see CubeVtx)
+    ao: [u8; 4],  // the ambient occlusion of each corner, from 0 (dark) to OPEN
+    tint: Tint,  // none if the face isn't tinted
+}
+
+// a full block face the greedy mesher can merge with the same faces next to it
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+struct MergeFace {
+    look: FaceLook,  // faces only merge with the same occlusion on every corner
+    climate: [u32; 4],  // on the four block corners around the face; tinted faces only merge with the same climate, so a quad has the tint of the faces it covers
+    layer: Layer,
+}
+
 // Cube Mesh
 // - stores all the mesh info
 // - to get a block from the mesh, you must retrieve it from a mesh struct like Cube
+// - there is a cube mesh for every layer, each with its own pipeline: the cutout one discards the
+//   transparent pixels, and the translucent one blends without writing the depth
 
 pub mod vs { vulkano_shaders::shader!{ty: "vertex", path: "resource/shaders/cube.vert",} }
 pub mod fs { vulkano_shaders::shader!{ty: "fragment", path: "resource/shaders/cube.frag",} }
 
 pub struct Cube {
-    pub texture: Rc<TextureAtlas>,  // texture image
-    chunk_data: Vec<(ChunkID, Vec<<Cube as Mesh>::Vertex>, Vec<u32>)>, // (chunk id, vert data, index data)
+    pub texture: Arc<TextureAtlas>,  // texture image
+    pub chunk_data: ChunkBuffers<CubeVtx>,  // the blocks of this layer in every chunk
+    entity_data: Vec<([f32; 3], MeshBuffers<CubeVtx>)>,  // (position, buffers) of the falling blocks and items; drawn after the chunks
+    layer: Layer,  // the blocks this mesh draws
     // pub index: Vec<u32>,
     sampler: Arc<Sampler>,  // texture sampler
+    tiles: Arc<CpuAccessibleBuffer<[Tile]>>,  // the tile table of the vertex shader (see Meshes::new)
     vtx_shader: vs::Shader,
     frg_shader: fs::Shader,
 }
 
 impl Cube {
-    pub fn new(device: Arc<Device>, texture: Rc<TextureAtlas>) -> Cube {
+    pub fn new(device: Arc<Device>, texture: Arc<TextureAtlas>, tiles: Arc<CpuAccessibleBuffer<[Tile]>>, layer: Layer) -> Cube {
         // Filter::Nearest for rendering each pixel instead of "smudging" between the adjacent pixels
         let sampler = Sampler::new(device.clone(), Filter::Nearest, Filter::Nearest,
                                    MipmapMode::Nearest, SamplerAddressMode::Repeat, SamplerAddressMode::Repeat,
                                    SamplerAddressMode::Repeat, 0.0, 1.0, 0.0, 0.0).unwrap();
 
-        Cube { texture: texture.clone(), sampler: sampler, chunk_data: Vec::new(),
+        Cube { texture: texture.clone(), sampler: sampler, chunk_data: ChunkBuffers::default(), entity_data: Vec::new(), layer, tiles,
             vtx_shader: vs::Shader::load(device.clone()).expect("failed to create cube vertex shaders module"),
             frg_shader: fs::Shader::load(device.clone()).expect("failed to create cube fragment shaders module")
         }
     }
 
+    // whether the neighbour hides the face of the block it touches
+    // - transparent blocks only hide the faces of the same block (e.g. glass next to glass)
+    fn hides(block: &Block, neighbour: &Block, face: usize) -> bool {
+        neighbour.id != "air"
+            && (!neighbour.transparent || neighbour.id == block.id)
+            && neighbour.shape.covers(neighbour.orientation(), face^1)  // the opposite face
+    }
+
+    // whether the block darkens the corners of the faces next to it
+    fn occludes(block: &Block) -> bool {
+        block.id != "air" && !block.transparent && block.shape == Shape::Full
+    }
+
+    // pushes the face of the box in the block at origin (in blocks from the origin of the draw) to
+    // the vertices and indices of the mesh
+    // - climate gives the climate of the block corner (x, z) nearest to each vertex, for the tint
+    // - the shader finds the texture coordinates from the position, so the texture is cropped to the
+    //   part of the block the face covers (see CubeVtx)
+    fn push_face(mesh: &mut (Vec<CubeVtx>, Vec<u32>), origin: [usize; 3], aabb: &Aabb, face: usize, look: FaceLook,
+                 climate: &dyn Fn(usize, usize) -> u32) {
+        let (vertices, indices) = mesh;
+        let FaceLook { tile, turn, ao, tint } = look;
+        let (normal, u, v) = (NORMALS[face], U[face], V[face]);
+        for (k, &(s, t)) in QUAD_CORNERS.iter().enumerate() {
+            let mut position = [0; 3];
+            for i in 0..3 {
+                let low = if u[i] != 0 {(s == 0) == (u[i] > 0)} else if v[i] != 0 {(t == 0) == (v[i] > 0)} else {normal[i] < 0};
+                let corner = if low {aabb.min[i]} else {aabb.max[i]};
+                position[i] = ((origin[i] as f32+corner)*16.0).round() as u32;
+            }
+            let climate = if tint == Tint::None {0} else {climate(((position[0]+8)/16) as usize, ((position[2]+8)/16) as usize)};
+            vertices.push(CubeVtx::new(position, face, ao[k], turn, tint, tile, climate));
+        }
+
+        // split along the brighter diagonal, so the occlusion looks the same however the face is turned
+        let index = vertices.len() as u32-VERT_PER_FACE;
+        if ao[1] as u32+ao[3] as u32 > ao[0] as u32+ao[2] as u32 {
+            indices.extend_from_slice(&[index+1, index+2, index+3, index+1, index+3, index]);
+        } else {
+            indices.extend_from_slice(&[index, index+1, index+2, index, index+2, index+3]);
+        }
+    }
+
+    // the vertices and indices of an entity, from its position; the same for every entity of the
+    // same kind and block
+    pub fn mesh_entity(entity: &Entity) -> (Vec<CubeVtx>, Vec<u32>) {
+        let mut mesh = (Vec::new(), Vec::new());
+        let block = &entity.block;
+        let aabb = entity.aabb();
+        for (face, &(block_face, corners)) in block.orientation().faces().iter().enumerate() {
+            let look = FaceLook { tile: block.tiles[block_face], turn: Self::turn(corners), ao: [OPEN; 4], tint: Self::tint(block, face) };
+            Self::push_face(&mut mesh, [0; 3], &aabb, face, look, &|_, _| ENTITY_CLIMATE);
+        }
+        mesh
+    }
+
+    // keeps the uploaded entities, replacing the ones before
+    pub fn onload_entities(&mut self, entity_data: Vec<([f32; 3], MeshBuffers<CubeVtx>)>) {
+        self.entity_data = entity_data;
+    }
+
+    pub fn entity_data(&self) -> Vec<([f32; 3], MeshBuffers<CubeVtx>)> {
+        self.entity_data.clone()
+    }
+
+    // the turn of the texture on a face (see CubeVtx) from the texture corners on the corners of the face
+    fn turn(corners: [usize; 4]) -> u8 {
+        let mirrored = corners[1] == (corners[0]+3)%4;
+        corners[0] as u8 | if mirrored {4} else {0}
+    }
+
+    // the biome tint of the face; grass only tints its top face
+    fn tint(block: &Block, face: usize) -> Tint {
+        if block.tint == Tint::Foliage || (block.tint == Tint::Grass && face == 0) {block.tint} else {Tint::None}
+    }
+
+    // the vertices and indices of the cube mesh blocks of a chunk, for every layer (see LAYERS)
+    // - the vertices are within the chunk; its position comes with the draw
+    pub fn mesh(mesher: Mesher, position: [f32; 3], block_data: &[Block], tints: &TintMap, neighbours: &Neighbours) -> Vec<(Vec<CubeVtx>, Vec<u32>)> {
+        let start = [
+            position.clone()[0] as usize,
+            position.clone()[1] as usize,
+            position.clone()[2] as usize
+        ];
+        let end = [
+            position.clone()[0] as usize+CHUNK_SIZE-1,
+            position.clone()[1] as usize+CHUNK_SIZE-1,
+            position.clone()[2] as usize+CHUNK_SIZE-1
+        ];
+
+        let mut layers: Vec<(Vec<CubeVtx>, Vec<u32>)> = LAYERS.iter().map(|_| (Vec::new(), Vec::new())).collect();
+
+        // the full faces the greedy mesher merges, by face and block location; 0 is no face, the
+        // rest are indices into merge_faces (+1)
+        let mut merge = vec![vec![0u32; CHUNK_SIZE*CHUNK_SIZE*CHUNK_SIZE]; if mesher == Mesher::Greedy {6} else {0}];
+        let mut merge_faces: Vec<MergeFace> = Vec::new();
+        let mut merge_index: HashMap<MergeFace, usize> = HashMap::new();
+
+        let get_loc = |x, y, z| (x%CHUNK_SIZE)*CHUNK_SIZE*CHUNK_SIZE+(y%CHUNK_SIZE)*CHUNK_SIZE+(z%CHUNK_SIZE);  // the block location on the data
+
+        // the neighbouring block; across the chunk border it is in the neighbouring chunk, and none
+        // if that chunk isn't loaded (the chunk gets meshed again once it is)
+        let neighbour = |x: usize, y: usize, z: usize, offset: [i32; 3]| {
+            let pos = [x as i64+offset[0] as i64, y as i64+offset[1] as i64, z as i64+offset[2] as i64];
+            for i in 0..3 {
+                if pos[i] < start[i] as i64 || pos[i] > end[i] as i64 {
+                    return neighbours.block(pos);
+                }
+            }
+            Some(&block_data[get_loc(pos[0] as usize, pos[1] as usize, pos[2] as usize)])
+        };
+
+        for x in start[0]..=end[0] {
+            for y in start[1]..=end[1] {
+                for z in start[2]..=end[2] {
+                    let block = &block_data[get_loc(x, y, z)];
+                    if block.mesh != MeshType::Cube || block.id == "air" {
+                        continue;
+                    }
+
+                    let orientation = block.orientation();
+                    let block_faces = orientation.faces();
+
+                    // a face on the border of the block is hidden if the neighbour fully covers it
+                    let mut hidden = [false; 6];
+                    for face in 0..6 {
+                        hidden[face] = match neighbour(x, y, z, NORMALS[face]) {
+                            Some(other) => Self::hides(block, other, face),
+                            None => false,
+                        };
+                    }
+
+                    // the connected texture variant of each face from the same blocks around it on
+                    // the plane of the face (see TextureAtlas::connected_coord)
+                    let mut variant = [0; 6];
+                    if !block.connected.is_empty() {
+                        for face in 0..6 {
+                            let (u, v) = (U[face], V[face]);
+                            let edges = [[-v[0], -v[1], -v[2]], u, v, [-u[0], -u[1], -u[2]]];  // top, right, bottom, left
+                            for (i, &edge) in edges.iter().enumerate() {
+                                if neighbour(x, y, z, edge).is_some_and(|other| other.id == block.id) {
+                                    variant[face] |= 1 << i;
+                                }
+                            }
+                        }
+                    }
+
+                    // the ambient occlusion of the corners of a face from the blocks in front of it
+                    // touching the corner: the two on the sides and the one on the corner
+                    let occlusion = |face: usize| {
+                        let (n, u, v) = (NORMALS[face], U[face], V[face]);
+                        let solid = |du: i32, dv: i32| {
+                            let offset = [n[0]+du*u[0]+dv*v[0], n[1]+du*u[1]+dv*v[1], n[2]+du*u[2]+dv*v[2]];
+                            neighbour(x, y, z, offset).is_some_and(Self::occludes)
+                        };
+                        let mut ao = [OPEN; 4];
+                        for (k, &(s, t)) in QUAD_CORNERS.iter().enumerate() {
+                            let (du, dv) = (s as i32*2-1, t as i32*2-1);
+                            let (side1, side2, corner) = (solid(du, 0), solid(0, dv), solid(du, dv));
+                            ao[k] = if side1 && side2 {0} else {OPEN-side1 as u8-side2 as u8-corner as u8};
+                        }
+                        ao
+                    };
+
+                    let mut connected = [false; 4];
+                    if block.shape.connecting() {
+                        for (i, offset) in CONNECTIONS.iter().enumerate() {
+                            let offset = [offset[0] as i32, offset[1] as i32, offset[2] as i32];
+                            connected[i] = neighbour(x, y, z, offset).is_some_and(|other| block.shape.connects(other));
+                        }
+                    }
+
+                    for aabb in block.shape.boxes(orientation, connected) {
+                        for face in 0..6 {
+                            let normal = NORMALS[face];
+                            let axis = (0..3).find(|&i| normal[i] != 0).unwrap();
+                            let on_border = if normal[axis] > 0 {aabb.max[axis] >= 1.0} else {aabb.min[axis] <= 0.0};
+                            if on_border && hidden[face] {
+                                continue;
+                            }
+
+                            // connected variants are chosen on the mesh face, so they skip the rotation
+                            let (block_face, corners) = block_faces[face];
+                            let (tile, turn) = if block.connected.is_empty() {
+                                (block.tiles[block_face], Self::turn(corners))
+                            } else {
+                                (block.connected[block_face*CONNECTED_VARIANTS+variant[face]], 0)
+                            };
+                            let ao = if on_border {occlusion(face)} else {[OPEN; 4]};
+                            let tint = Self::tint(block, face);
+                            let look = FaceLook { tile, turn, ao, tint };
+
+                            // full faces are left for the greedy mesher to merge
+                            if mesher == Mesher::Greedy && block.shape == Shape::Full {
+                                let (lx, lz) = (x-start[0], z-start[2]);
+                                let climate = if tint == Tint::None {[0; 4]} else {
+                                    [tints.climate(lx, lz), tints.climate(lx+1, lz), tints.climate(lx, lz+1), tints.climate(lx+1, lz+1)]
+                                };
+                                let merge_face = MergeFace { look, climate, layer: block.layer };
+                                let index = *merge_index.entry(merge_face).or_insert_with(|| {
+                                    merge_faces.push(merge_face);
+                                    merge_faces.len()-1
+                                });
+                                merge[face][get_loc(x, y, z)] = index as u32+1;
+                                continue;
+                            }
+
+                            Self::push_face(&mut layers[block.layer as usize], [x-start[0], y-start[1], z-start[2]], &aabb, face, look,
+                                            &|x, z| tints.climate(x, z));
+                        }
+                    }
+                }
+            }
+        }
+
+        if mesher == Mesher::Greedy {
+            Self::merge_faces(&mut layers, &merge, &merge_faces, tints);
+        }
+        layers
+    }
+
+    // merges the faces next to each other with the same texture, occlusion, tint, climate and layer into larger
+    // quads, row by row and then by the rows below with the same faces
+    // - the texture repeats on every block of a quad (see cube.frag)
+    fn merge_faces(layers: &mut [(Vec<CubeVtx>, Vec<u32>)], merge: &[Vec<u32>], merge_faces: &[MergeFace], tints: &TintMap) {
+        let axis = |dir: [i32; 3]| (0..3).find(|&i| dir[i] != 0).unwrap();
+        let mut mask = vec![0u32; CHUNK_SIZE*CHUNK_SIZE];
+        for face in 0..6 {
+            let (n, u, v) = (axis(NORMALS[face]), axis(U[face]), axis(V[face]));
+            let (u_up, v_up) = (U[face][u] > 0, V[face][v] > 0);
+            // the block at (i, j) of the slice, with i and j going along the u and v of the texture
+            let local = |slice: usize, i: usize, j: usize| {
+                let mut local = [0; 3];
+                local[n] = slice;
+                local[u] = if u_up {i} else {CHUNK_SIZE-1-i};
+                local[v] = if v_up {j} else {CHUNK_SIZE-1-j};
+                local
+            };
+
+            for slice in 0..CHUNK_SIZE {
+                for j in 0..CHUNK_SIZE {
+                    for i in 0..CHUNK_SIZE {
+                        let [x, y, z] = local(slice, i, j);
+                        mask[j*CHUNK_SIZE+i] = merge[face][x*CHUNK_SIZE*CHUNK_SIZE+y*CHUNK_SIZE+z];
+                    }
+                }
+
+                for j in 0..CHUNK_SIZE {
+                    let mut i = 0;
+                    while i < CHUNK_SIZE {
+                        let index = mask[j*CHUNK_SIZE+i];
+                        if index == 0 {
+                            i += 1;
+                            continue;
+                        }
+
+                        let mut width = 1;
+                        while i+width < CHUNK_SIZE && mask[j*CHUNK_SIZE+i+width] == index {
+                            width += 1;
+                        }
+                        let mut height = 1;
+                        while j+height < CHUNK_SIZE && mask[(j+height)*CHUNK_SIZE+i..(j+height)*CHUNK_SIZE+i+width].iter().all(|&m| m == index) {
+                            height += 1;
+                        }
+                        for row in j..j+height {
+                            for m in mask[row*CHUNK_SIZE+i..row*CHUNK_SIZE+i+width].iter_mut() {
+                                *m = 0;
+                            }
+                        }
+
+                        // the quad as a box reaching from the block at (i, j) over the blocks it covers
+                        let mut aabb = Aabb::new([0.0; 3], [1.0; 3]);
+                        if u_up {aabb.max[u] = width as f32} else {aabb.min[u] = 1.0-width as f32}
+                        if v_up {aabb.max[v] = height as f32} else {aabb.min[v] = 1.0-height as f32}
+
+                        let merge_face = &merge_faces[index as usize-1];
+                        Self::push_face(&mut layers[merge_face.layer as usize], local(slice, i, j), &aabb, face, merge_face.look,
+                                        &|x, z| tints.climate(x, z));
+
+                        i += width;
+                    }
+                }
+            }
+        }
+    }
+
     pub fn descriptors<'b, U: Send+Sync+'b, A: MemoryPool+Sync+'b>(&self, pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>, sub_buf: &CpuBufferPoolSubbuffer<U, A>) -> Vec<Arc<dyn DescriptorSet+Send+Sync+'b>>
         where <A as MemoryPool>::Alloc: Send+Sync
     {
         let layout0 = pipeline.descriptor_set_layout(0).unwrap();
         let set0 = Arc::new(PersistentDescriptorSet::start(layout0.clone())
             .add_sampled_image(self.texture.texture.clone(), self.sampler.clone()).unwrap()
+            .add_buffer(self.tiles.clone()).unwrap()
             .build().unwrap()
         );
 
@@ -110,7 +437,9 @@ impl Mesh for Cube {
                 render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
                 dimensions: [u32; 2])
                 -> Arc<dyn GraphicsPipelineAbstract + Send + Sync> {
-        Arc::new(GraphicsPipeline::start()
+        // only the cutout pixels are ever discarded, so the opaque blocks keep the early depth test
+        let cutoff = fs::SpecializationConstants { ALPHA_CUTOFF: if self.layer == Layer::Cutout {0.5} else {0.0} };
+        let pipeline = GraphicsPipeline::start()
             .vertex_input_single_buffer::<Self::Vertex>()
             .vertex_shader(self.vtx_shader.main_entry_point(), ())
             .triangle_list()
@@ -120,148 +449,261 @@ impl Mesh for Cube {
                 dimensions: [dimensions[0] as f32, dimensions[1] as f32],
                 depth_range: 0.0 .. 1.0,
             }))
-            .fragment_shader(self.frg_shader.main_entry_point(), ())
-            .cull_mode_front()  // face culling for optimization
-            .alpha_to_coverage_enabled()
-            .depth_stencil_simple_depth()
+            .fragment_shader(self.frg_shader.main_entry_point(), cutoff)
+            .cull_mode_front();  // face culling for optimization
+        // the translucent blocks are blended over what is behind them, and don't hide the ones
+        // behind them in the same chunk
+        let pipeline = match self.layer {
+            Layer::Translucent => pipeline
+                .blend_alpha_blending()
+                .depth_stencil(DepthStencil { depth_write: false, .. DepthStencil::simple_depth_test() }),
+            _ => pipeline.depth_stencil_simple_depth(),
+        };
+        Arc::new(pipeline
             .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
             .build(device.clone()).unwrap()
         )
     }
+}
 
-    fn onload_data(&mut self, chunk_id: ChunkID, position: [f32; 3], block_data: &Vec<Block>) {
-        let start = [
-            position.clone()[0] as usize,
-            position.clone()[1] as usize,
-            position.clone()[2] as usize
-        ];
-        let end = [
-            position.clone()[0] as usize+CHUNK_SIZE-1,
-            position.clone()[1] as usize+CHUNK_SIZE-1,
-            position.clone()[2] as usize+CHUNK_SIZE-1
-        ];
-        println!("START {:?}; END {:?}", start, end);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::test_block;
+    use crate::chunk::{Chunk, ChunkID};
+    use crate::texture::MAX_TILES;
+    use crate::generator::climate::Climate;
+    use crate::orientation::Orientation;
+
+    // the fields packed into the vertex, unpacked the way cube.vert does (see CubeVtx)
+    fn position(v: &CubeVtx) -> [f32; 3] {
+        [(v.data[0]&0x7FF) as f32/16.0, (v.data[0]>>11&0x7FF) as f32/16.0, (v.data[1]&0x7FF) as f32/16.0]
+    }
 
-        let mut vertices = Vec::with_capacity(CHUNK_SIZE*CHUNK_SIZE*CHUNK_SIZE*VERT_PER_CUBE as usize);
-        let mut indices: Vec<u32> = Vec::with_capacity(CHUNK_SIZE*CHUNK_SIZE*CHUNK_SIZE*IND_PER_CUBE as usize);
+    fn ao(v: &CubeVtx) -> u32 {
+        v.data[0]>>25&3
+    }
 
-        let get_loc = |x, y, z| (x%CHUNK_SIZE)*CHUNK_SIZE*CHUNK_SIZE+(y%CHUNK_SIZE)*CHUNK_SIZE+(z%CHUNK_SIZE);  // the block location on the data
+    fn tile(v: &CubeVtx) -> u32 {
+        v.data[1]>>13&0x1FF
+    }
 
-        for x in start[0]..=end[0] {
-            for y in start[1]..=end[1] {
-                for z in start[2]..=end[2] {
-                    let block: Block = block_data[get_loc(x, y, z)].clone();
-
-                    if block.mesh == MeshType::Cube {
-                        let mut faces = 0u8;
-
-                        // if if (1st: checks chunk border) {true} else {2nd: checks for nearby transparent block}
-                        if if start[0] == x {true} else {block_data[get_loc(x-1, y, z)].clone().transparent && !block.transparent} {  // left face
-                            vertices.push(Self::Vertex { position: [0.0+x as f32,0.0+y as f32,1.0+z as f32], txtr_crd: block.texture_coord[2][3], });
-                            vertices.push(Self::Vertex { position: [0.0+x as f32,1.0+y as f32,1.0+z as f32], txtr_crd: block.texture_coord[2][0], });
-                            vertices.push(Self::Vertex { position: [0.0+x as f32,1.0+y as f32,0.0+z as f32], txtr_crd: block.texture_coord[2][1], });
-                            vertices.push(Self::Vertex { position: [0.0+x as f32,0.0+y as f32,0.0+z as f32], txtr_crd: block.texture_coord[2][2], });
-                            faces += 1;
-                        }
-                        if if start[1] == y {true} else {(block_data[get_loc(x, y-1, z)].clone().transparent && !block.transparent)} {  // bottom face
-                            vertices.push(Self::Vertex { position: [0.0+x as f32,0.0+y as f32,0.0+z as f32], txtr_crd: block.texture_coord[1][0], });
-                            vertices.push(Self::Vertex { position: [1.0+x as f32,0.0+y as f32,0.0+z as f32], txtr_crd: block.texture_coord[1][1], });
-                            vertices.push(Self::Vertex { position: [1.0+x as f32,0.0+y as f32,1.0+z as f32], txtr_crd: block.texture_coord[1][2], });
-                            vertices.push(Self::Vertex { position: [0.0+x as f32,0.0+y as f32,1.0+z as f32], txtr_crd: block.texture_coord[1][3], });
-                            faces += 1;
-                        }
-                        if if start[2] == z {true} else {(block_data[get_loc(x, y, z-1)].clone().transparent && !block.transparent)} {  // front face
-                            vertices.push(Self::Vertex { position: [0.0+x as f32,1.0+y as f32,0.0+z as f32], txtr_crd: block.texture_coord[4][0], });
-                            vertices.push(Self::Vertex { position: [1.0+x as f32,1.0+y as f32,0.0+z as f32], txtr_crd: block.texture_coord[4][1], });
-                            vertices.push(Self::Vertex { position: [1.0+x as f32,0.0+y as f32,0.0+z as f32], txtr_crd: block.texture_coord[4][2], });
-                            vertices.push(Self::Vertex { position: [0.0+x as f32,0.0+y as f32,0.0+z as f32], txtr_crd: block.texture_coord[4][3], });
-                            faces += 1;
-                        }
-                        if if end[0] == x {true} else {(block_data[get_loc(x+1, y, z)].clone().transparent && !block.transparent)} {  // right face
-                            vertices.push(Self::Vertex { position: [1.0+x as f32,0.0+y as f32,0.0+z as f32], txtr_crd: block.texture_coord[3][3], });
-                            vertices.push(Self::Vertex { position: [1.0+x as f32,1.0+y as f32,0.0+z as f32], txtr_crd: block.texture_coord[3][0], });
-                            vertices.push(Self::Vertex { position: [1.0+x as f32,1.0+y as f32,1.0+z as f32], txtr_crd: block.texture_coord[3][1], });
-                            vertices.push(Self::Vertex { position: [1.0+x as f32,0.0+y as f32,1.0+z as f32], txtr_crd: block.texture_coord[3][2], });
-                            faces += 1;
-                        }
-                        if if end[1] == y {true} else {(block_data[get_loc(x, y+1, z)].clone().transparent && !block.transparent)} {  // top face
-                            vertices.push(Self::Vertex { position: [0.0+x as f32,1.0+y as f32,1.0+z as f32], txtr_crd: block.texture_coord[0][0], });
-                            vertices.push(Self::Vertex { position: [1.0+x as f32,1.0+y as f32,1.0+z as f32], txtr_crd: block.texture_coord[0][1], });
-                            vertices.push(Self::Vertex { position: [1.0+x as f32,1.0+y as f32,0.0+z as f32], txtr_crd: block.texture_coord[0][2], });
-                            vertices.push(Self::Vertex { position: [0.0+x as f32,1.0+y as f32,0.0+z as f32], txtr_crd: block.texture_coord[0][3], });
-                            faces += 1;
-                        }
-                        if if end[2] == z {true} else {(block_data[get_loc(x, y, z+1)].clone().transparent && !block.transparent)} {  // back face
-                            vertices.push(Self::Vertex { position: [0.0+x as f32,0.0+y as f32,1.0+z as f32], txtr_crd: block.texture_coord[5][2], });
-                            vertices.push(Self::Vertex { position: [1.0+x as f32,0.0+y as f32,1.0+z as f32], txtr_crd: block.texture_coord[5][3], });
-                            vertices.push(Self::Vertex { position: [1.0+x as f32,1.0+y as f32,1.0+z as f32], txtr_crd: block.texture_coord[5][0], });
-                            vertices.push(Self::Vertex { position: [0.0+x as f32,1.0+y as f32,1.0+z as f32], txtr_crd: block.texture_coord[5][1], });
-                            faces += 1;
-                        }
+    fn face(v: &CubeVtx) -> u32 {
+        v.data[0]>>22&7
+    }
 
-                        for _ in 0..faces {
-                            if indices.is_empty() {
-                                indices.append(
-                                    &mut vec![
-                                        0, 1, 2,
-                                        0, 2, 3,
-                                    ]
-                                )
-                            } else {
-                                indices.append(
-                                    &mut vec![
-                                        0, 1, 2,
-                                        0, 2, 3,
-                                    ].iter().map(|&x| x+*indices.last().unwrap() as u32+1).collect()
-                                )
-                            }
-                        }
-                    }
+    fn turn(v: &CubeVtx) -> u32 {
+        v.data[0]>>27&7
+    }
+
+    fn tinted(v: &CubeVtx) -> u32 {
+        v.data[1]>>11&3
+    }
+
+    fn climate(v: &CubeVtx) -> [u32; 2] {
+        [v.data[1]>>22&31, v.data[1]>>27]
+    }
+
+    // a block with its own tile in a 4x4 atlas
+    fn block(id: &'static str, tile: usize, shape: Shape, tint: Tint) -> Block {
+        let (x, y) = ((tile%4) as f32/4.0, (tile/4) as f32/4.0);
+        Block {
+            shape,
+            texture_coord: vec![[[x, y], [x+0.25, y], [x+0.25, y+0.25], [x, y+0.25]]; 6],
+            tiles: [tile as u32; 6],
+            tint,
+            ..test_block(id)
+        }
+    }
+
+    fn chunk(block: impl Fn(usize, usize, usize) -> Block) -> Vec<Block> {
+        let mut blocks = Vec::with_capacity(CHUNK_SIZE*CHUNK_SIZE*CHUNK_SIZE);
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    blocks.push(block(x, y, z));
                 }
             }
         }
+        blocks
+    }
+
+    // the number of quads and the area they cover
+    fn quads(mesher: Mesher, blocks: &[Block]) -> (usize, f32) {
+        let tints = Climate::new(7).tint_map(&[0, 0, 0], CHUNK_SIZE);
+        let (vertices, indices) = Cube::mesh(mesher, [0.0; 3], blocks, &tints, &Neighbours::new(ChunkID(0, 0, 0), &[])).swap_remove(Layer::Opaque as usize);
+        assert_eq!(vertices.len()/4*6, indices.len());
+
+        let area = vertices.chunks(4).map(|quad| {
+            let sub = |a: [f32; 3], b: [f32; 3]| [a[0]-b[0], a[1]-b[1], a[2]-b[2]];
+            let (a, b) = (sub(position(&quad[1]), position(&quad[0])), sub(position(&quad[3]), position(&quad[0])));
+            let cross = [a[1]*b[2]-a[2]*b[1], a[2]*b[0]-a[0]*b[2], a[0]*b[1]-a[1]*b[0]];
+            (cross[0]*cross[0]+cross[1]*cross[1]+cross[2]*cross[2]).sqrt()
+        }).sum();
+        (vertices.len()/4, area)
+    }
+
+    fn compare(blocks: &[Block]) -> ((usize, f32), (usize, f32)) {
+        let (naive, greedy) = (quads(Mesher::Naive, blocks), quads(Mesher::Greedy, blocks));
+        assert!((naive.1-greedy.1).abs() < 1e-3, "naive area {} but greedy area {}", naive.1, greedy.1);
+        assert!(greedy.0 <= naive.0);
+        (naive, greedy)
+    }
 
-        self.chunk_data.push((chunk_id, vertices, indices));
+    #[test]
+    fn vertices_unpack_to_what_they_were_packed_from() {
+        // (position, face, ao, turn, tint, tile, climate), from the lowest to the highest of every field
+        let fields = [
+            ([0, 0, 0], 0, 0, 0, (Tint::None, 0), 0, [0, 0]),
+            ([512, 17, 2047], 5, 3, 7, (Tint::Foliage, 2), MAX_TILES as u32-1, [31, 31]),
+            ([5, 2047, 80], 2, 1, 4, (Tint::Grass, 1), 300, [20, 8]),
+        ];
+        for &(pos, face_id, occlusion, texture_turn, (tint, kind), tile_id, [t, h]) in fields.iter() {
+            let vertex = CubeVtx::new(pos, face_id, occlusion, texture_turn, tint, tile_id, t | h << 5);
+            assert_eq!(position(&vertex), [pos[0] as f32/16.0, pos[1] as f32/16.0, pos[2] as f32/16.0]);
+            assert_eq!((face(&vertex), ao(&vertex), turn(&vertex)), (face_id as u32, occlusion as u32, texture_turn as u32));
+            assert_eq!((tinted(&vertex), tile(&vertex), climate(&vertex)), (kind, tile_id, [t, h]));
+        }
     }
 
-    fn offload_chunk(&self, chunk: &Chunk) {
+    #[test]
+    fn flat_floor_is_six_quads() {
+        let stone = block("stone", 1, Shape::Full, Tint::None);
+        let air = block("air", 0, Shape::Full, Tint::None);
+        let blocks = chunk(|_, y, _| if y == 0 {stone.clone()} else {air.clone()});
+
+        let ((naive, area), (greedy, _)) = compare(&blocks);
+        let size = CHUNK_SIZE as f32;
+        assert_eq!(area, 2.0*size*size + 4.0*size);
+        assert_eq!(naive, 2*CHUNK_SIZE*CHUNK_SIZE + 4*CHUNK_SIZE);
+        assert_eq!(greedy, 6);
+    }
 
+    #[test]
+    fn greedy_texture_repeats_per_block() {
+        let stone = block("stone", 1, Shape::Full, Tint::None);
+        let air = block("air", 0, Shape::Full, Tint::None);
+        let blocks = chunk(|x, y, z| if y == 0 && x < 5 && z < 3 {stone.clone()} else {air.clone()});
+
+        let tints = Climate::new(7).tint_map(&[0, 0, 0], CHUNK_SIZE);
+        let (vertices, _) = Cube::mesh(Mesher::Greedy, [0.0; 3], &blocks, &tints, &Neighbours::new(ChunkID(0, 0, 0), &[])).swap_remove(Layer::Opaque as usize);
+        let top = vertices.chunks(4).find(|quad| quad.iter().all(|v| position(v)[1] == 1.0)).unwrap();
+        let span = |i: usize| top.iter().map(|v| position(v)[i]).fold(f32::NEG_INFINITY, f32::max)-top.iter().map(|v| position(v)[i]).fold(f32::INFINITY, f32::min);
+        assert_eq!((span(0), span(2)), (5.0, 3.0));
+        assert!(top.iter().all(|v| tile(v) == 1));
     }
 
-    fn retrieve_vert(&mut self, chunks: &Vec<Chunk>) -> Vec<Self::Vertex> {
-        println!("Chunk datas in Cube Mesh: {:?}", self.chunk_data.len());
-        let mut vtx_data = Vec::new();
+    #[test]
+    fn greedy_keeps_the_tint_of_every_corner() {
+        let grass = block("grass", 3, Shape::Full, Tint::Grass);
+        let air = block("air", 0, Shape::Full, Tint::None);
+        let blocks = chunk(|_, y, _| if y == 0 {grass.clone()} else {air.clone()});
+
+        // far enough from the origin for the climate to change within the chunk
+        let tints = Climate::new(7).tint_map(&[4096, 0, 4096], CHUNK_SIZE);
+        let (vertices, _) = Cube::mesh(Mesher::Greedy, [0.0; 3], &blocks, &tints, &Neighbours::new(ChunkID(0, 0, 0), &[])).swap_remove(Layer::Opaque as usize);
+        let top: Vec<&CubeVtx> = vertices.iter().filter(|v| v.data[0]>>22&7 == 0).collect();
+        assert!(top.len() > 4);
+        for v in top {
+            let p = position(v);
+            assert_eq!(v.data[1]>>22, tints.climate(p[0] as usize, p[2] as usize));
+        }
+    }
 
-        for (chunk_id, vertices, _indices) in self.chunk_data.iter() {
-            if chunks.iter().filter(|c| &c.id == chunk_id).last().unwrap().visible { // TODO: safety check and map ID's when offloading chunk is used
-                println!("YES");
-                vtx_data.extend(vertices.iter());
+    #[test]
+    fn mixed_terrain_covers_the_same_area() {
+        let air = block("air", 0, Shape::Full, Tint::None);
+        let stone = block("stone", 1, Shape::Full, Tint::None);
+        let dirt = block("dirt", 2, Shape::Full, Tint::None);
+        let grass = block("grass", 3, Shape::Full, Tint::Grass);
+        let slab = block("slab", 4, Shape::Slab, Tint::None);
+        let mut log = block("log", 5, Shape::Full, Tint::None);
+        log.state.orientation = Orientation::Axis(Axis::X);
+
+        let blocks = chunk(|x, y, z| {
+            let height = 20+(x*7/CHUNK_SIZE)+((z/9)%3);
+            match y {
+                _ if y == height && (x+z)%11 == 0 => slab.clone(),
+                _ if y > height+4 && y < height+7 && x%13 == 1 => log.clone(),
+                _ if y > height => air.clone(),
+                _ if y == height => grass.clone(),
+                _ if y+3 > height => dirt.clone(),
+                _ => stone.clone(),
             }
+        });
+
+        let ((naive, _), (greedy, _)) = compare(&blocks);
+        assert!(greedy*4 < naive, "greedy {} quads, naive {} quads", greedy, naive);
+    }
+
+    #[test]
+    fn full_blocks_keep_their_faces_next_to_slabs() {
+        let stone = block("stone", 1, Shape::Full, Tint::None);
+        let slab = block("slab", 4, Shape::Slab, Tint::None);
+        let air = block("air", 0, Shape::Full, Tint::None);
+
+        // only a slab on top (its bottom is covered) hides the face of the block
+        for face in 0..6 {
+            assert_eq!(Cube::hides(&stone, &slab, face), face == 0, "face {}", face);
         }
-        println!("Vertices retrieved: {:?}", vtx_data.len());
-        vtx_data
-    }
-
-    fn retrieve_ind(&mut self, chunks: &Vec<Chunk>) -> Vec<u32> {
-        // TODO: index can be pre-computed on the run without hassling chaing the indexes since chunk visibility varies
-        let mut ind_data: Vec<u32> = Vec::new();
-        let mut index: u32 = 0;
-
-        for (chunk_id, _vertices, indices) in self.chunk_data.iter() {
-            if chunks.iter().filter(|c| &c.id == chunk_id).last().unwrap().visible {
-                if ind_data.is_empty() {
-                    ind_data.extend(
-                        indices.iter()
-                    );
-                } else {
-                    let ind_index = (*ind_data.last().unwrap()).clone();
-                    ind_data.extend(
-                        indices.iter().map(|&x| x+ind_index+1)
-                    );
-                }
-                index += 1;
-            }
+
+        // the side of the block next to the slab is meshed
+        let blocks = chunk(|x, y, z| match (x, y, z) {
+            (5, 5, 5) => stone.clone(),
+            (6, 5, 5) => slab.clone(),
+            _ => air.clone(),
+        });
+        let tints = Climate::new(7).tint_map(&[0, 0, 0], CHUNK_SIZE);
+        let (vertices, _) = Cube::mesh(Mesher::Naive, [0.0; 3], &blocks, &tints, &Neighbours::new(ChunkID(0, 0, 0), &[])).swap_remove(Layer::Opaque as usize);
+        assert!((0..vertices.len()/4).any(|q| {
+            vertices[q*4..q*4+4].iter().map(position).all(|p| p[0] == 6.0 && p[1] >= 5.0 && p[1] <= 6.0 && p[2] >= 5.0 && p[2] <= 6.0)
+                && vertices[q*4..q*4+4].iter().any(|v| tile(v) == 1)
+        }));
+    }
+
+    #[test]
+    fn occlusion_darkens_the_corner_next_to_a_block() {
+        let stone = block("stone", 1, Shape::Full, Tint::None);
+        let air = block("air", 0, Shape::Full, Tint::None);
+        let blocks = chunk(|x, y, z| if y == 0 || (x, y, z) == (5, 1, 5) {stone.clone()} else {air.clone()});
+
+        let tints = Climate::new(7).tint_map(&[0, 0, 0], CHUNK_SIZE);
+        let (vertices, indices) = Cube::mesh(Mesher::Naive, [0.0; 3], &blocks, &tints, &Neighbours::new(ChunkID(0, 0, 0), &[])).swap_remove(Layer::Opaque as usize);
+
+        // the top of the floor block diagonal to the block on the floor
+        let quad = (0..vertices.len()/4).find(|&q| {
+            vertices[q*4..q*4+4].iter().map(position).all(|p| p[1] == 1.0 && p[0] >= 6.0 && p[2] >= 6.0 && p[0] <= 7.0 && p[2] <= 7.0)
+        }).unwrap();
+        let corners: Vec<u32> = vertices[quad*4..quad*4+4].iter().map(ao).collect();
+        let dark = vertices[quad*4..quad*4+4].iter().position(|v| position(v) == [6.0, 1.0, 6.0]).unwrap();
+        for (k, &value) in corners.iter().enumerate() {
+            assert_eq!(value, if k == dark {2} else {3});
+        }
+
+        // the dark corner isn't on the diagonal, so it is in one triangle only
+        let triangles = &indices[quad*6..quad*6+6];
+        assert_eq!(triangles.iter().filter(|&&i| i == (quad*4+dark) as u32).count(), 1);
+    }
+
+    #[test]
+    fn occlusion_looks_into_the_chunk_on_the_corner() {
+        let stone = block("stone", 1, Shape::Full, Tint::None);
+        let air = block("air", 0, Shape::Full, Tint::None);
+
+        // a block on the corner of chunk (1, 1, 1) and one diagonally below it, in chunk (0, 0, 0)
+        let end = CHUNK_SIZE-1;
+        let corner = Chunk::new(ChunkID(0, 0, 0), [0, 0, 0], chunk(|x, y, z| if (x, y, z) == (end, end, end) {stone.clone()} else {air.clone()}),
+                                Climate::new(7).tint_map(&[0, 0, 0], CHUNK_SIZE));
+        let blocks = chunk(|x, y, z| if (x, y, z) == (0, 0, 0) {stone.clone()} else {air.clone()});
+        let origin = CHUNK_SIZE as f32;
+        let tints = Climate::new(7).tint_map(&[CHUNK_SIZE as u32; 3], CHUNK_SIZE);
+        let (vertices, _) = Cube::mesh(Mesher::Naive, [origin; 3], &blocks, &tints, &Neighbours::new(ChunkID(1, 1, 1), &[corner]))
+            .swap_remove(Layer::Opaque as usize);
+
+        // the corner of the bottom face over the block in the other chunk is darker
+        let bottom = (0..vertices.len()/4).find(|&q| vertices[q*4..q*4+4].iter().all(|v| position(v)[1] == 0.0)).unwrap();
+        for v in vertices[bottom*4..bottom*4+4].iter() {
+            assert_eq!(ao(v), if position(v) == [0.0; 3] {2} else {3}, "{:?}", position(v));
         }
-        ind_data
     }
 }
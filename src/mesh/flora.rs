@@ -1,57 +1,101 @@
-use crate::renderer::CubeVtx;
-use crate::texture::TextureAtlas;
-use crate::chunk::{CHUNK_SIZE, ChunkID};
-use crate::chunk::Chunk;
-use crate::renderer;
+use crate::renderer::FloraVtx;
+use crate::texture::{TextureAtlas, Tile};
+use crate::chunk::CHUNK_SIZE;
 use crate::block::Block;
+use crate::generator::climate::TintMap;
+use crate::mesh::cube::QUAD_CORNERS;
 use crate::mesh::mesh::{
+    ChunkBuffers,
     Mesh,
-    MeshType
+    MeshType,
 };
 
 
 use vulkano::device::Device;
-use vulkano::image::{SwapchainImage};
 use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract, viewport::Viewport};
 use vulkano::framebuffer::{Subpass, RenderPassAbstract};
 use vulkano::descriptor::{descriptor_set::PersistentDescriptorSet, DescriptorSet};
 use vulkano::sampler::{Sampler, Filter, MipmapMode, SamplerAddressMode};
 use vulkano::memory::pool::MemoryPool;
+use vulkano::buffer::{CpuAccessibleBuffer, cpu_pool::CpuBufferPoolSubbuffer};
+
 use std::sync::Arc;
 use std::iter;
-use winit::window::Window;
-use vulkano::buffer::cpu_pool::CpuBufferPoolSubbuffer;
 
 
+// the two quads crossing the block from corner to corner; the corners go top left, top right,
+// bottom right and bottom left like the texture corners
+const CROSSES: [[[f32; 3]; 4]; 2] = [
+    [[0.0, 1.0, 0.0], [1.0, 1.0, 1.0], [1.0, 0.0, 1.0], [0.0, 0.0, 0.0]],
+    [[0.0, 1.0, 1.0], [1.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+];
+
 
 // Flora Mesh
 // - 2 x shape
 // - both of the two shapes are perpendicular to each other
 // - rotated 45 deg to differentiate itself from blocks
+// - the quads are seen from both sides (no face culling), and the transparent pixels of the
+//   texture are cut out in the fragment shader instead of blended
 
-pub mod vs { vulkano_shaders::shader!{ty: "vertex", path: "resource/shaders/cube.vert",} }
-pub mod fs { vulkano_shaders::shader!{ty: "fragment", path: "resource/shaders/cube.frag",} }
+pub mod vs { vulkano_shaders::shader!{ty: "vertex", path: "resource/shaders/flora.vert",} }
+pub mod fs { vulkano_shaders::shader!{ty: "fragment", path: "resource/shaders/flora.frag",} }
 
 pub struct Flora {
-    pub texture: TextureAtlas,  // texture image
-    chunk_data: Vec<(ChunkID, Vec<<Flora as Mesh>::Vertex>, Vec<u32>)>,
-    // pub index: Vec<u32>,
+    pub texture: Arc<TextureAtlas>,  // texture image
+    pub chunk_data: ChunkBuffers<FloraVtx>,  // the plants in every chunk
     sampler: Arc<Sampler>,  // texture sampler
+    tiles: Arc<CpuAccessibleBuffer<[Tile]>>,  // the tile table of the vertex shader (see Meshes::new)
     vtx_shader: vs::Shader,
     frg_shader: fs::Shader,
 }
 
 impl Flora {
-    pub fn new(device: Arc<Device>, texture: TextureAtlas) -> Flora {
+    pub fn new(device: Arc<Device>, texture: Arc<TextureAtlas>, tiles: Arc<CpuAccessibleBuffer<[Tile]>>) -> Flora {
         // Filter::Nearest for rendering each pixel instead of "smudging" between the adjacent pixels
         let sampler = Sampler::new(device.clone(), Filter::Nearest, Filter::Nearest,
                                    MipmapMode::Nearest, SamplerAddressMode::Repeat, SamplerAddressMode::Repeat,
                                    SamplerAddressMode::Repeat, 0.0, 1.0, 0.0, 0.0).unwrap();
 
-        Flora { texture: texture, sampler: sampler, chunk_data: Vec::new(),
-            vtx_shader: vs::Shader::load(device.clone()).expect("failed to create cube vertex shaders module"),
-            frg_shader: fs::Shader::load(device.clone()).expect("failed to create cube fragment shaders module")
+        Flora { texture, sampler, chunk_data: ChunkBuffers::default(), tiles,
+            vtx_shader: vs::Shader::load(device.clone()).expect("failed to create flora vertex shaders module"),
+            frg_shader: fs::Shader::load(device.clone()).expect("failed to create flora fragment shaders module")
+        }
+    }
+
+    // the vertices and indices of the flora mesh blocks of a chunk
+    // - plants are never hidden by the blocks around them, so the neighbouring chunks don't matter
+    // - the vertices are within the chunk; its position comes with the draw
+    pub fn mesh(block_data: &[Block], tints: &TintMap) -> (Vec<FloraVtx>, Vec<u32>) {
+        let mut vertices = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    let block = &block_data[x*CHUNK_SIZE*CHUNK_SIZE+y*CHUNK_SIZE+z];
+                    if block.mesh != MeshType::Flora || block.id == "air" {
+                        continue;
+                    }
+
+                    // both quads show the front texture
+                    for cross in CROSSES.iter() {
+                        let index = vertices.len() as u32;
+                        for (corner, &(s, t)) in cross.iter().zip(QUAD_CORNERS.iter()) {
+                            vertices.push(FloraVtx {
+                                position: [x as f32+corner[0], y as f32+corner[1], z as f32+corner[2]],
+                                txtr_crd: [s as f32, t as f32],
+                                tile: block.tiles[4],
+                                tint: tints.corner(block.tint, x+corner[0] as usize, z+corner[2] as usize),
+                            });
+                        }
+                        indices.extend_from_slice(&[index, index+1, index+2, index, index+2, index+3]);
+                    }
+                }
+            }
         }
+
+        (vertices, indices)
     }
 
     pub fn descriptors<'b, U: Send+Sync+'b, A: MemoryPool+Sync+'b>(&self, pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>, sub_buf: &CpuBufferPoolSubbuffer<U, A>) -> Vec<Arc<dyn DescriptorSet+Send+Sync+'b>>
@@ -60,6 +104,7 @@ impl Flora {
         let layout0 = pipeline.descriptor_set_layout(0).unwrap();
         let set0 = Arc::new(PersistentDescriptorSet::start(layout0.clone())
             .add_sampled_image(self.texture.texture.clone(), self.sampler.clone()).unwrap()
+            .add_buffer(self.tiles.clone()).unwrap()
             .build().unwrap()
         );
 
@@ -73,15 +118,13 @@ impl Flora {
 }
 
 impl Mesh for Flora {
-    type Vertex = CubeVtx;
+    type Vertex = FloraVtx;
 
     fn pipeline(&self,
                 device: Arc<Device>,
                 render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
-                images: &Vec<Arc<SwapchainImage<Window>>>)
+                dimensions: [u32; 2])
                 -> Arc<dyn GraphicsPipelineAbstract + Send + Sync> {
-        let dimensions = images[0].dimensions();
-
         Arc::new(GraphicsPipeline::start()
             .vertex_input_single_buffer::<Self::Vertex>()
             .vertex_shader(self.vtx_shader.main_entry_point(), ())
@@ -93,115 +136,9 @@ impl Mesh for Flora {
                 depth_range: 0.0 .. 1.0,
             }))
             .fragment_shader(self.frg_shader.main_entry_point(), ())
-            .cull_mode_front()  // face culling for optimization
-            .alpha_to_coverage_enabled()
+            .cull_mode_disabled()  // both sides of the quads are seen
             .depth_stencil_simple_depth()
             .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
             .build(device.clone()).unwrap())
     }
-
-    fn onload_data(&mut self, chunk: ChunkID, position: [f32; 3], block_data: &Vec<Block>) {
-        let start = position.clone();
-        let end = [position[0]+CHUNK_SIZE as f32-1.0, position[1]+CHUNK_SIZE as f32-1.0, position[2]+CHUNK_SIZE as f32-1.0];
-
-        let mut vertices = Vec::with_capacity(CHUNK_SIZE*CHUNK_SIZE*CHUNK_SIZE*6*4);
-        let mut indices: Vec<u32> = Vec::with_capacity(CHUNK_SIZE*CHUNK_SIZE*CHUNK_SIZE*6*6);
-
-        for x in 0..CHUNK_SIZE {
-            for y in 0..CHUNK_SIZE {
-                for z in 0..CHUNK_SIZE {
-                    let index = x*CHUNK_SIZE*CHUNK_SIZE+y*CHUNK_SIZE+z;  // the block location on the data
-                    let block = block_data[index];
-                    if block.mesh == MeshType::Cube {
-                        let top = self.texture.texture_coord(block.texture[0][0],block.texture[0][1]);
-                        let bottom = self.texture.texture_coord(block.texture[1][0],block.texture[1][1]);
-                        let left = self.texture.texture_coord(block.texture[2][0],block.texture[2][1]);
-                        let right = self.texture.texture_coord(block.texture[3][0],block.texture[3][1]);
-                        let front = self.texture.texture_coord(block.texture[4][0],block.texture[4][1]);
-                        let back = self.texture.texture_coord(block.texture[5][0],block.texture[5][1]);
-
-                        if start[0] == position[0] {
-                            vertices.push(Self::Vertex { position: [0.0+position[0],0.0+position[1],1.0+position[2]], txtr_crd: left[3], });
-                            vertices.push(Self::Vertex { position: [0.0+position[0],1.0+position[1],1.0+position[2]], txtr_crd: left[0], });
-                            vertices.push(Self::Vertex { position: [0.0+position[0],1.0+position[1],0.0+position[2]], txtr_crd: left[1], });
-                            vertices.push(Self::Vertex { position: [0.0+position[0],0.0+position[1],0.0+position[2]], txtr_crd: left[2], });
-                        }
-                        if start[1] == position[1] {
-                            vertices.push(Self::Vertex { position: [0.0+position[0],0.0+position[1],0.0+position[2]], txtr_crd: bottom[0], });
-                            vertices.push(Self::Vertex { position: [1.0+position[0],0.0+position[1],0.0+position[2]], txtr_crd: bottom[1], });
-                            vertices.push(Self::Vertex { position: [1.0+position[0],0.0+position[1],1.0+position[2]], txtr_crd: bottom[2], });
-                            vertices.push(Self::Vertex { position: [0.0+position[0],0.0+position[1],1.0+position[2]], txtr_crd: bottom[3], });
-                        }
-                        if start[2] == position[2] {
-                            vertices.push(Self::Vertex { position: [0.0+position[0],1.0+position[1],0.0+position[2]], txtr_crd: front[0], });
-                            vertices.push(Self::Vertex { position: [1.0+position[0],1.0+position[1],0.0+position[2]], txtr_crd: front[1], });
-                            vertices.push(Self::Vertex { position: [1.0+position[0],0.0+position[1],0.0+position[2]], txtr_crd: front[2], });
-                            vertices.push(Self::Vertex { position: [0.0+position[0],0.0+position[1],0.0+position[2]], txtr_crd: front[3], });
-                        }
-                        if end[0] == position[0] {
-                            vertices.push(Self::Vertex { position: [1.0+position[0],0.0+position[1],0.0+position[2]], txtr_crd: right[3], });
-                            vertices.push(Self::Vertex { position: [1.0+position[0],1.0+position[1],0.0+position[2]], txtr_crd: right[0], });
-                            vertices.push(Self::Vertex { position: [1.0+position[0],1.0+position[1],1.0+position[2]], txtr_crd: right[1], });
-                            vertices.push(Self::Vertex { position: [1.0+position[0],0.0+position[1],1.0+position[2]], txtr_crd: right[2], });
-                        }
-                        if end[1] == position[1] {
-                            vertices.push(Self::Vertex { position: [0.0+position[0],1.0+position[1],1.0+position[2]], txtr_crd: top[0], });
-                            vertices.push(Self::Vertex { position: [1.0+position[0],1.0+position[1],1.0+position[2]], txtr_crd: top[1], });
-                            vertices.push(Self::Vertex { position: [1.0+position[0],1.0+position[1],0.0+position[2]], txtr_crd: top[2], });
-                            vertices.push(Self::Vertex { position: [0.0+position[0],1.0+position[1],0.0+position[2]], txtr_crd: top[3], });
-                        }
-                        if end[2] == position[2] {
-                            vertices.push(Self::Vertex { position: [0.0+position[0],0.0+position[1],1.0+position[2]], txtr_crd: back[2], });
-                            vertices.push(Self::Vertex { position: [1.0+position[0],0.0+position[1],1.0+position[2]], txtr_crd: back[3], });
-                            vertices.push(Self::Vertex { position: [1.0+position[0],1.0+position[1],1.0+position[2]], txtr_crd: back[0], });
-                            vertices.push(Self::Vertex { position: [0.0+position[0],1.0+position[1],1.0+position[2]], txtr_crd: back[1], });
-                        }
-
-                        indices.append(
-                            &mut vec![
-                                0, 1, 2, 0, 2, 3,
-                                4, 5, 6, 4, 6, 7,
-                                8, 9,10, 8,10,11,
-                                12,13,14,12,14,15,
-                                16,17,18,16,18,19,
-                                20,21,22,20,22,23,].iter().map(|&x| x+(index*6*4) as u32).collect()
-                        );
-                    }
-                }
-            }
-        }
-
-        self.chunk_data.push((chunk, vertices, indices));
-    }
-
-    fn offload_chunk(&self, chunk: &Chunk) {
-
-    }
-
-    fn retrieve_vert(&mut self, chunk_data: &Vec<Chunk>) -> Vec<Self::Vertex> {
-        let mut vtx_data = Vec::new();
-
-        for (chunk, vertices, _indices) in self.chunk_data.iter() {
-            if chunk_data[chunk.0 as usize].visible {
-                vtx_data.append(&mut vertices.clone());
-            }
-        }
-        vtx_data
-    }
-
-    fn retrieve_ind(&mut self, chunk_data: &Vec<Chunk>) -> Vec<u32> {
-        // TODO: index can be pre-computed on the run without hassling chaing the indexes since chunk visibility varies
-        let mut ind_data = Vec::new();
-        let mut index = 0;
-
-        for (chunk, _vertices, indices) in self.chunk_data.iter() {
-            if chunk_data[chunk.0 as usize].visible {
-                ind_data.append(
-                    &mut indices.iter().map(|&x| x+(&index*6*4)).collect()
-                );
-                index += 1;
-            }
-        }
-        ind_data
-    }
 }
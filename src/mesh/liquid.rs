@@ -0,0 +1,351 @@
+use crate::renderer::LiquidVtx;
+use crate::texture::{TextureAtlas, Tile};
+use crate::chunk::{CHUNK_SIZE, Neighbours};
+use crate::block::Block;
+use crate::generator::climate::TintMap;
+use crate::orientation::{NORMALS, U, V};
+use crate::mesh::cube::QUAD_CORNERS;
+use crate::mesh::mesh::{
+    ChunkBuffers,
+    Mesh,
+    MeshType,
+};
+
+
+use vulkano::device::Device;
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract, viewport::Viewport};
+use vulkano::pipeline::depth_stencil::DepthStencil;
+use vulkano::framebuffer::{Subpass, RenderPassAbstract};
+use vulkano::descriptor::{descriptor_set::PersistentDescriptorSet, DescriptorSet};
+use vulkano::sampler::{Sampler, Filter, MipmapMode, SamplerAddressMode};
+use vulkano::memory::pool::MemoryPool;
+use vulkano::buffer::{CpuAccessibleBuffer, cpu_pool::CpuBufferPoolSubbuffer};
+
+use std::sync::Arc;
+use std::iter;
+
+
+const LEVELS: f32 = 9.0;  // a source (level 0) fills 8/9 of the block, and every level below it 1/9 less
+
+
+// Liquid Mesh
+// - water and lava; the surface sits lower the farther the liquid flowed from its source (its
+//   level), and is full height with the same liquid above it
+// - the corners of the surface are the average of the liquid around them, so the surface slopes
+//   down between the blocks; the sides go up to the corners of the surface
+// - the faces between the same liquid are hidden, and so are the faces against opaque blocks
+// - the faces get the direction the liquid flows in along their texture, which the shader scrolls
+//   the texture in: down the slope on the surface, and down the sides of flowing liquid
+// - drawn last, blended over the rest without writing the depth
+
+pub mod vs { vulkano_shaders::shader!{ty: "vertex", path: "resource/shaders/liquid.vert",} }
+pub mod fs { vulkano_shaders::shader!{ty: "fragment", path: "resource/shaders/liquid.frag",} }
+
+pub struct Liquid {
+    pub texture: Arc<TextureAtlas>,  // texture image
+    pub chunk_data: ChunkBuffers<LiquidVtx>,  // the liquid in every chunk
+    sampler: Arc<Sampler>,  // texture sampler
+    tiles: Arc<CpuAccessibleBuffer<[Tile]>>,  // the tile table of the vertex shader (see Meshes::new)
+    vtx_shader: vs::Shader,
+    frg_shader: fs::Shader,
+}
+
+impl Liquid {
+    pub fn new(device: Arc<Device>, texture: Arc<TextureAtlas>, tiles: Arc<CpuAccessibleBuffer<[Tile]>>) -> Liquid {
+        // Filter::Nearest for rendering each pixel instead of "smudging" between the adjacent pixels
+        let sampler = Sampler::new(device.clone(), Filter::Nearest, Filter::Nearest,
+                                   MipmapMode::Nearest, SamplerAddressMode::Repeat, SamplerAddressMode::Repeat,
+                                   SamplerAddressMode::Repeat, 0.0, 1.0, 0.0, 0.0).unwrap();
+
+        Liquid { texture, sampler, chunk_data: ChunkBuffers::default(), tiles,
+            vtx_shader: vs::Shader::load(device.clone()).expect("failed to create liquid vertex shaders module"),
+            frg_shader: fs::Shader::load(device.clone()).expect("failed to create liquid fragment shaders module")
+        }
+    }
+
+    // whether the neighbour hides the face of the liquid it touches
+    // - the surface is below the top of the block, so only the same liquid hides it
+    fn hides(liquid: &Block, neighbour: &Block, face: usize) -> bool {
+        neighbour.id == liquid.id
+            || (face != 0 && neighbour.mesh == MeshType::Cube && neighbour.id != "air" && !neighbour.transparent
+                && neighbour.shape.covers(neighbour.orientation(), face^1))  // the opposite face
+    }
+
+    // the vertices and indices of the liquid blocks of a chunk
+    // - the vertices are within the chunk; its position comes with the draw
+    pub fn mesh(position: [f32; 3], block_data: &[Block], tints: &TintMap, neighbours: &Neighbours) -> (Vec<LiquidVtx>, Vec<u32>) {
+        let start = [position[0] as i64, position[1] as i64, position[2] as i64];
+        let size = CHUNK_SIZE as i64;
+        let mut vertices = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+
+        // the block at the world position; across the chunk border it is in the neighbouring chunk
+        let block_at = |pos: [i64; 3]| {
+            if (0..3).all(|i| pos[i] >= start[i] && pos[i] < start[i]+size) {
+                Some(&block_data[((pos[0]-start[0])*size*size+(pos[1]-start[1])*size+(pos[2]-start[2])) as usize])
+            } else {
+                neighbours.block(pos)
+            }
+        };
+
+        // the height of the surface of the liquid at the position; none if it is another block
+        let surface = |liquid: &Block, pos: [i64; 3]| {
+            let block = block_at(pos).filter(|b| b.id == liquid.id)?;
+            if block_at([pos[0], pos[1]+1, pos[2]]).is_some_and(|b| b.id == liquid.id) {
+                Some(1.0)
+            } else {
+                Some((8-block.level()) as f32/LEVELS)
+            }
+        };
+
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    let block = &block_data[x*CHUNK_SIZE*CHUNK_SIZE+y*CHUNK_SIZE+z];
+                    if block.mesh != MeshType::Liquid || block.id == "air" {
+                        continue;
+                    }
+                    let pos = [start[0]+x as i64, start[1]+y as i64, start[2]+z as i64];
+
+                    // the surface height on each corner of the block, by [x][z]; full if the liquid
+                    // around the corner has the same liquid above it
+                    let corner_height = |cx: i64, cz: i64| {
+                        let mut around = Vec::with_capacity(4);
+                        for dx in cx-1..=cx {
+                            for dz in cz-1..=cz {
+                                around.extend(surface(block, [pos[0]+dx, pos[1], pos[2]+dz]));
+                            }
+                        }
+                        // there is always the block itself
+                        if around.iter().any(|&height| height >= 1.0) {1.0} else {around.iter().sum::<f32>()/around.len() as f32}
+                    };
+                    let heights = [[corner_height(0, 0), corner_height(0, 1)], [corner_height(1, 0), corner_height(1, 1)]];
+
+                    // the surface flows down the slope, in x and z
+                    let slope = [
+                        heights[0][0]+heights[0][1]-heights[1][0]-heights[1][1],
+                        heights[0][0]+heights[1][0]-heights[0][1]-heights[1][1],
+                    ];
+                    let falling = block.level() > 0 || surface(block, pos) == Some(1.0);
+
+                    for face in 0..6 {
+                        let (normal, u, v) = (NORMALS[face], U[face], V[face]);
+                        if block_at([pos[0]+normal[0] as i64, pos[1]+normal[1] as i64, pos[2]+normal[2] as i64])
+                            .is_some_and(|other| Self::hides(block, other, face)) {
+                            continue;
+                        }
+
+                        // the direction of the flow along the u and v of the face
+                        let flow = match face {
+                            0 => {
+                                let along = |dir: [i32; 3]| slope[0]*dir[0] as f32+slope[1]*dir[2] as f32;
+                                let (s, t) = (along(u), along(v));
+                                let length = (s*s+t*t).sqrt();
+                                if length > 0.001 {[s/length, t/length]} else {[0.0, 0.0]}
+                            },
+                            1 => [0.0, 0.0],
+                            _ => if falling {[0.0, 1.0]} else {[0.0, 0.0]},  // v goes down on the sides
+                        };
+
+                        let index = vertices.len() as u32;
+                        for &(s, t) in QUAD_CORNERS.iter() {
+                            // the corner of the block, with the top at the height of the surface
+                            let mut corner = [0.0f32; 3];
+                            for i in 0..3 {
+                                let low = if u[i] != 0 {(s == 0) == (u[i] > 0)} else if v[i] != 0 {(t == 0) == (v[i] > 0)} else {normal[i] < 0};
+                                corner[i] = if low {0.0} else {1.0};
+                            }
+                            if corner[1] > 0.0 {
+                                corner[1] = heights[corner[0] as usize][corner[2] as usize];
+                            }
+
+                            // the texture isn't stretched on the sides that are lower than a block
+                            let along = |dir: [i32; 3]| {
+                                let i = (0..3).find(|&i| dir[i] != 0).unwrap();
+                                if dir[i] > 0 {corner[i]} else {1.0-corner[i]}
+                            };
+                            let (s, t) = (along(u), along(v));
+
+                            vertices.push(LiquidVtx {
+                                position: [x as f32+corner[0], y as f32+corner[1], z as f32+corner[2]],
+                                txtr_crd: [s, t],
+                                tile: block.tiles[face],
+                                tint: tints.corner(block.tint, x+corner[0] as usize, z+corner[2] as usize),
+                                flow,
+                            });
+                        }
+                        indices.extend_from_slice(&[index, index+1, index+2, index, index+2, index+3]);
+                    }
+                }
+            }
+        }
+
+        (vertices, indices)
+    }
+
+    pub fn descriptors<'b, U: Send+Sync+'b, A: MemoryPool+Sync+'b>(&self, pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>, sub_buf: &CpuBufferPoolSubbuffer<U, A>) -> Vec<Arc<dyn DescriptorSet+Send+Sync+'b>>
+        where <A as MemoryPool>::Alloc: Send+Sync
+    {
+        let layout0 = pipeline.descriptor_set_layout(0).unwrap();
+        let set0 = Arc::new(PersistentDescriptorSet::start(layout0.clone())
+            .add_sampled_image(self.texture.texture.clone(), self.sampler.clone()).unwrap()
+            .add_buffer(self.tiles.clone()).unwrap()
+            .build().unwrap()
+        );
+
+        let layout1 = pipeline.descriptor_set_layout(1).unwrap();
+        let set1 = Arc::new(PersistentDescriptorSet::start(layout1.clone())
+            .add_buffer(sub_buf.clone()).unwrap()
+            .build().unwrap()
+        );
+        vec![set0, set1]
+    }
+}
+
+impl Mesh for Liquid {
+    type Vertex = LiquidVtx;
+
+    fn pipeline(&self,
+                device: Arc<Device>,
+                render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+                dimensions: [u32; 2])
+                -> Arc<dyn GraphicsPipelineAbstract + Send + Sync> {
+        Arc::new(GraphicsPipeline::start()
+            .vertex_input_single_buffer::<Self::Vertex>()
+            .vertex_shader(self.vtx_shader.main_entry_point(), ())
+            .triangle_list()
+            .viewports_dynamic_scissors_irrelevant(1)
+            .viewports(iter::once(Viewport {
+                origin: [0.0, 0.0],
+                dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+                depth_range: 0.0 .. 1.0,
+            }))
+            .fragment_shader(self.frg_shader.main_entry_point(), ())
+            .cull_mode_disabled()  // the surface is seen from below as well
+            .blend_alpha_blending()
+            // tested against the depth of the rest, but doesn't hide what is drawn behind it after
+            .depth_stencil(DepthStencil { depth_write: false, .. DepthStencil::simple_depth_test() })
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .build(device.clone()).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{BlockTag, test_block};
+    use crate::chunk::ChunkID;
+    use crate::generator::climate::Climate;
+    use crate::mesh::mesh::Layer;
+
+    const LIQUID: [BlockTag; 1] = [BlockTag::Nibble("level")];
+
+    fn liquid(id: &'static str, level: u8) -> Block {
+        let mut block = Block { mesh: MeshType::Liquid, properties: &LIQUID, transparent: true, layer: Layer::Translucent, ..test_block(id) };
+        block.set_property("level", level).unwrap();
+        block
+    }
+
+    // the quads of the blocks in an empty chunk
+    fn quads(blocks: &[([usize; 3], Block)]) -> Vec<Vec<LiquidVtx>> {
+        let mut block_data = vec![test_block("air"); CHUNK_SIZE*CHUNK_SIZE*CHUNK_SIZE];
+        for ([x, y, z], block) in blocks {
+            block_data[x*CHUNK_SIZE*CHUNK_SIZE+y*CHUNK_SIZE+z] = block.clone();
+        }
+        let tints = Climate::new(7).tint_map(&[0, 0, 0], CHUNK_SIZE);
+        let (vertices, indices) = Liquid::mesh([0.0; 3], &block_data, &tints, &Neighbours::new(ChunkID(0, 0, 0), &[]));
+        assert_eq!(vertices.len()/4*6, indices.len());
+        vertices.chunks(4).map(|quad| quad.to_vec()).collect()
+    }
+
+    // the top and the rest of the faces of the block at the position
+    fn faces(quads: &[Vec<LiquidVtx>], pos: [usize; 3]) -> (&Vec<LiquidVtx>, Vec<&Vec<LiquidVtx>>) {
+        let within = |v: &LiquidVtx| (0..3).all(|i| v.position[i] >= pos[i] as f32 && v.position[i] <= pos[i] as f32+1.0);
+        let (top, rest): (Vec<_>, Vec<_>) = quads.iter()
+            .filter(|quad| quad.iter().all(within))
+            .partition(|quad| quad.iter().all(|v| v.position[1] > pos[1] as f32));
+        assert_eq!(top.len(), 1);
+        (top[0], rest)
+    }
+
+    // the direction of the flow on the top face in the world
+    fn flow(vertex: &LiquidVtx) -> [f32; 3] {
+        let mut world = [0.0; 3];
+        for i in 0..3 {
+            world[i] = vertex.flow[0]*U[0][i] as f32+vertex.flow[1]*V[0][i] as f32;
+        }
+        world
+    }
+
+    #[test]
+    fn the_surface_sinks_with_the_level() {
+        for level in 0..8 {
+            let quads = quads(&[([5, 5, 5], liquid("water", level))]);
+            assert_eq!(quads.len(), 6);
+            let height = 5.0+(8-level) as f32/LEVELS;
+            let (top, rest) = faces(&quads, [5, 5, 5]);
+            assert!(top.iter().all(|v| (v.position[1]-height).abs() < 1e-5), "level {}: {:?}", level, top);
+            // the sides reach up to the surface
+            for side in rest.iter().filter(|quad| quad.iter().any(|v| v.position[1] > 5.0)) {
+                let highest = side.iter().map(|v| v.position[1]).fold(f32::NEG_INFINITY, f32::max);
+                assert!((highest-height).abs() < 1e-5, "level {}: {:?}", level, side);
+            }
+        }
+
+        // under the same liquid it is full, and the top is hidden
+        let quads = quads(&[([5, 5, 5], liquid("water", 3)), ([5, 6, 5], liquid("water", 3))]);
+        assert_eq!(quads.len(), 10);
+        assert!(quads.iter().flatten().filter(|v| v.position[1] > 5.0 && v.position[1] < 6.5).all(|v| v.position[1] == 6.0));
+    }
+
+    #[test]
+    fn corners_average_the_liquid_around_them() {
+        let quads = quads(&[([5, 5, 5], liquid("water", 0)), ([6, 5, 5], liquid("water", 4))]);
+        let (source, flowing) = (5.0+8.0/LEVELS, 5.0+4.0/LEVELS);
+        let shared = (source+flowing)/2.0;
+        // the corners between the two are the average of both
+        for (pos, own) in [([5, 5, 5], source), ([6, 5, 5], flowing)].iter() {
+            let (top, _) = faces(&quads, *pos);
+            for v in top.iter() {
+                let expected = if v.position[0] == 6.0 {shared} else {*own};
+                assert!((v.position[1]-expected).abs() < 1e-5, "{:?}", v);
+            }
+        }
+    }
+
+    #[test]
+    fn faces_hide_between_the_same_liquid_only() {
+        let water = liquid("water", 0);
+        assert_eq!(quads(&[([5, 5, 5], water.clone())]).len(), 6);
+        assert_eq!(quads(&[([5, 5, 5], water.clone()), ([6, 5, 5], water.clone())]).len(), 10);
+        assert_eq!(quads(&[([5, 5, 5], water.clone()), ([5, 4, 5], water.clone())]).len(), 10);
+        // other liquids and air don't hide it, but opaque blocks do
+        assert_eq!(quads(&[([5, 5, 5], water.clone()), ([6, 5, 5], liquid("lava", 0))]).len(), 12);
+        assert_eq!(quads(&[([5, 5, 5], water.clone()), ([6, 5, 5], test_block("stone"))]).len(), 5);
+    }
+
+    #[test]
+    fn the_flow_points_from_high_to_low() {
+        // lower water next to the source on every side
+        for offset in [[1, 0, 0], [-1, 0, 0], [0, 0, 1], [0, 0, -1]].iter() {
+            let low = [(5+offset[0]) as usize, 5, (5+offset[2]) as usize];
+            let quads = quads(&[([5, 5, 5], liquid("water", 0)), (low, liquid("water", 4))]);
+            for pos in [[5, 5, 5], low].iter() {
+                let (top, rest) = faces(&quads, *pos);
+                for v in top.iter() {
+                    let world = flow(v);
+                    assert!((0..3).all(|i| (world[i]-offset[i] as f32).abs() < 1e-5), "towards {:?}: {:?}", offset, world);
+                }
+                // the source doesn't fall down its sides, but the flowing water does
+                let falling = if *pos == low {[0.0, 1.0]} else {[0.0, 0.0]};
+                for side in rest.iter().filter(|quad| quad.iter().any(|v| v.position[1] > 5.0)) {
+                    assert!(side.iter().all(|v| v.flow == falling), "{:?}", side);
+                }
+            }
+        }
+
+        // still water doesn't flow
+        let quads = quads(&[([5, 5, 5], liquid("water", 0))]);
+        assert!(quads.iter().flatten().all(|v| v.flow == [0.0, 0.0]));
+    }
+}
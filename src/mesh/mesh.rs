@@ -1,28 +1,141 @@
-use crate::renderer::CubeVtx;
-use crate::block::Block;
-use crate::chunk::{Chunk, ChunkID};
+use crate::renderer::{CubeVtx, FloraVtx, LiquidVtx};
+use crate::entity::{Entity, EntityKind};
+use crate::chunk::{Chunk, ChunkID, Neighbours, CHUNK_SIZE};
+use crate::block::{Block, BlockState};
+use crate::generator::climate::TintMap;
 
-use vulkano::device::Device;
+use serde::Deserialize;
+
+use vulkano::device::{Device, Queue};
 use vulkano::image::{SwapchainImage};
 use vulkano::pipeline::GraphicsPipelineAbstract;
 use vulkano::framebuffer::RenderPassAbstract;
-use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer, cpu_pool::CpuBufferPoolSubbuffer};
+use vulkano::buffer::{BufferAccess, BufferUsage, CpuAccessibleBuffer, ImmutableBuffer, cpu_pool::CpuBufferPoolSubbuffer};
 use vulkano::descriptor::DescriptorSet;
 use vulkano::memory::MemoryPool;
+use vulkano::sync::GpuFuture;
 use winit::window::Window;
 
-use std::rc::Rc;
 use std::sync::Arc;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::iter;
 
 
-#[derive(Eq, PartialEq, Copy, Clone)]
+#[derive(Eq, PartialEq, Copy, Clone, Deserialize)]
 pub enum MeshType {
     Cube,  // 6 side cube
     Flora,  // x-shape
+    Liquid,  // water and lava; the surface height follows the flow level
     // Particle
     // Custom
 }
 
+// the pass a block is drawn in
+#[derive(Eq, PartialEq, Copy, Clone, Hash, Debug, Deserialize)]
+pub enum Layer {
+    Opaque,  // every pixel is drawn
+    Cutout,  // the transparent pixels are cut out (glass, leaves, plants)
+    Translucent,  // blended over what is behind it (water, stained glass)
+}
+
+pub const LAYERS: [Layer; 3] = [Layer::Opaque, Layer::Cutout, Layer::Translucent];
+
+// the pipelines the meshes are drawn with, in the order of Meshes::retrieve_pipeline; a draw
+// refers to its pipeline (and its descriptor sets) by it
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Pipeline {
+    OpaqueCube,
+    CutoutCube,  // and the entities
+    Flora,
+    TranslucentCube,
+    Liquid,
+}
+
+pub const PIPELINES: [Pipeline; 5] = [Pipeline::OpaqueCube, Pipeline::CutoutCube, Pipeline::Flora, Pipeline::TranslucentCube, Pipeline::Liquid];
+// the entities are drawn with the cutout blocks, which draws opaque blocks just as well
+const ENTITY_PIPELINE: Pipeline = Pipeline::CutoutCube;
+
+impl Pipeline {
+    // the layer the pipeline draws
+    pub fn layer(self) -> Layer {
+        match self {
+            Pipeline::OpaqueCube => Layer::Opaque,
+            Pipeline::CutoutCube | Pipeline::Flora => Layer::Cutout,
+            Pipeline::TranslucentCube | Pipeline::Liquid => Layer::Translucent,
+        }
+    }
+
+    // the position in the pipelines and descriptor sets handed out by Meshes
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+// the vertex and index buffers of one chunk (or an entity) in device local memory, uploaded once
+// when it gets meshed and kept until it is meshed again or unloads
+pub type MeshBuffers<V> = (Arc<ImmutableBuffer<[V]>>, Arc<ImmutableBuffer<[u32]>>);
+// the same, without the vertex type, so the meshes with different vertices can be drawn in one go
+pub type DrawBuffers = (Arc<dyn BufferAccess + Send + Sync>, Arc<ImmutableBuffer<[u32]>>);
+
+// the buffers of a chunk (or an entity) and the pipeline they are drawn with
+// - the origin goes to the shaders as a push constant (see cube.vert)
+pub struct Draw {
+    pub pipeline: Pipeline,
+    pub origin: [f32; 3],  // the position the cube vertices are relative to
+    pub buffers: DrawBuffers,
+}
+
+// the uploaded buffers of every meshed chunk of one mesh (and layer); none if the chunk has
+// nothing in it
+pub struct ChunkBuffers<V> {
+    chunks: Vec<(ChunkID, Option<MeshBuffers<V>>)>,
+}
+
+impl<V> Default for ChunkBuffers<V> {
+    fn default() -> Self {
+        Self { chunks: Vec::new() }
+    }
+}
+
+impl<V> ChunkBuffers<V> {
+    // keeps the uploaded mesh of the chunk, replacing the one before
+    pub fn insert(&mut self, chunk: ChunkID, buffers: Option<MeshBuffers<V>>) {
+        self.remove(chunk);
+        self.chunks.push((chunk, buffers));
+    }
+
+    // frees the buffers of the chunk
+    pub fn remove(&mut self, chunk: ChunkID) {
+        self.chunks.retain(|(id, _)| *id != chunk);
+    }
+
+    // the buffers of the visible chunks
+    pub fn visible(&self, chunks: &[Chunk]) -> Vec<(ChunkID, MeshBuffers<V>)> {
+        self.chunks.iter().filter_map(|(id, buffers)| {
+            let visible = chunks.iter().any(|c| c.id == *id && c.visible);
+            Some((*id, buffers.clone().filter(|_| visible)?))
+        }).collect()
+    }
+}
+
+// the meshes of a chunk, one for every mesh type; made by the workers
+pub struct ChunkMesh {
+    pub cube: Vec<(Vec<CubeVtx>, Vec<u32>)>,  // one per layer (see LAYERS)
+    pub flora: (Vec<FloraVtx>, Vec<u32>),
+    pub liquid: (Vec<LiquidVtx>, Vec<u32>),
+}
+
+impl ChunkMesh {
+    pub fn new(mesher: Mesher, position: [f32; 3], block_data: &[Block], tints: &TintMap, neighbours: &Neighbours) -> Self {
+        Self {
+            cube: Cube::mesh(mesher, position, block_data, tints, neighbours),
+            flora: Flora::mesh(block_data, tints),
+            liquid: Liquid::mesh(position, block_data, tints, neighbours),
+        }
+    }
+}
+
 pub trait Mesh {
     type Vertex: 'static;
 
@@ -31,43 +144,170 @@ pub trait Mesh {
                 render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
                 dimensions: [u32; 2])
         -> Arc<dyn GraphicsPipelineAbstract + Send + Sync>;  // returns the graphic pipeline of that mesh
-    fn onload_data(&mut self, chunk: ChunkID, position: [f32; 3], block_data: &Vec<Block>);  // updates the vertex data
-    fn offload_chunk(&self, chunk: &Chunk);
-    fn retrieve_vert(&mut self, chunk_data: &Vec<Chunk>) -> Vec<Self::Vertex>;
-    fn retrieve_ind(&mut self, chunk_data: &Vec<Chunk>) -> Vec<u32>;
 }
 
-use crate::mesh::cube::Cube;
-// use crate::mesh::flora::Flora;
-use crate::texture::TextureAtlas;
+use crate::mesh::cube::{Cube, Mesher};
+use crate::mesh::flora::Flora;
+use crate::mesh::liquid::Liquid;
+use crate::texture::{TextureAtlas, Tile, MAX_TILES};
 
+// Meshes
+// - the vertices of every mesh are relative to their chunk, which goes to the vertex shaders as
+//   the origin push constant, and the textures are tile ids the vertex shaders look up in the tile
+//   table (see TextureAtlas::tiles), which is uploaded once and shared by every pipeline
+// - every mesh type has its own pipeline, and the cube mesh has one for every layer; the pipelines
+//   and descriptor sets are handed out in the same order (see PIPELINES), and every draw
+//   refers to its pipeline by it
+// - the opaque and cutout layers are drawn first, then the translucent ones are blended over them
+//   chunk by chunk from back to front (see sort_draws)
+// - the meshes are copied into device local memory through staging buffers; the copies are only
+//   submitted with the next frame, which waits for them (see take_uploads)
 pub struct Meshes {
     // lines: u8,  // generic lines for debugging (chunk borders, etc.) or block borders
-    cube: Cube,
-    // flora: M,
+    cubes: Vec<Cube>,  // one per layer (see LAYERS)
+    flora: Flora,
+    liquid: Liquid,
+    mesher: Mesher,
+    queue: Arc<Queue>,  // the queue the copies go to
+    uploads: Option<Box<dyn GpuFuture>>,  // the copies since the last frame
+    // the mesh of every kind of entity there is, uploaded the first time one shows up and shared by
+    // all of them, and dropped once none is left; none if it has no faces
+    entity_meshes: HashMap<(EntityKind, &'static str, BlockState), Option<MeshBuffers<CubeVtx>>>,
 }
 
 impl Meshes {
-    pub fn new(device: Arc<Device>, txtr: Rc<TextureAtlas>) -> Self {
+    pub fn new(device: Arc<Device>, queue: Arc<Queue>, txtr: Arc<TextureAtlas>) -> Self {
+        // the tile table of the vertex shaders, filled up to MAX_TILES
+        let tiles: Vec<Tile> = txtr.tiles.iter().cloned().chain(iter::repeat([0.0; 8])).take(MAX_TILES).collect();
+        let tiles = CpuAccessibleBuffer::from_iter(device.clone(), BufferUsage::uniform_buffer(), false, tiles.into_iter()).unwrap();
         Self {
-            cube: Cube::new(device.clone(), txtr.clone()),
-            // flora: Flora,
+            cubes: LAYERS.iter().map(|&layer| Cube::new(device.clone(), txtr.clone(), tiles.clone(), layer)).collect(),
+            flora: Flora::new(device.clone(), txtr.clone(), tiles.clone()),
+            liquid: Liquid::new(device.clone(), txtr.clone(), tiles),
+            mesher: Mesher::Greedy,
+            queue,
+            uploads: None,
+            entity_meshes: HashMap::new(),
         }
     }
 
-    pub fn onload_data(&mut self, chunk: ChunkID, position: [f32; 3], block_data: &Vec<Block>) {
-        println!("ONLOADED {:?}", position);
-        self.cube.onload_data(chunk, position, block_data)
+    // uploads a mesh into its own buffers; empty meshes have none, since a buffer can't be empty
+    fn upload<V: Send + Sync + 'static>(&mut self, vertices: Vec<V>, indices: Vec<u32>) -> Option<MeshBuffers<V>> {
+        if indices.is_empty() {
+            return None;
+        }
+        let (vertex_buffer, vertex_copy) = ImmutableBuffer::from_iter(vertices.into_iter(),
+                                                                      BufferUsage::vertex_buffer(), self.queue.clone()).unwrap();
+        let (index_buffer, index_copy) = ImmutableBuffer::from_iter(indices.into_iter(),
+                                                                    BufferUsage::index_buffer(), self.queue.clone()).unwrap();
+        let copies = vertex_copy.join(index_copy);
+        self.uploads = Some(match self.uploads.take() {
+            Some(uploads) => Box::new(uploads.join(copies)),
+            None => Box::new(copies),
+        });
+        Some((vertex_buffer, index_buffer))
     }
 
-    pub fn retrieve_data(&mut self, device: Arc<Device>, chunk_data: &Vec<Chunk>) -> Vec<(Arc<CpuAccessibleBuffer<[CubeVtx]>>, Arc<CpuAccessibleBuffer<[u32]>>)> {
-        let vertex_buffer = CpuAccessibleBuffer::from_iter(device.clone(),
-                                                           BufferUsage::vertex_buffer(), false, self.cube.retrieve_vert(chunk_data).into_iter()).unwrap();
+    // the copies of the meshes uploaded since the last call; the buffers can't be drawn before them
+    pub fn take_uploads(&mut self) -> Option<Box<dyn GpuFuture>> {
+        self.uploads.take()
+    }
 
-        let index_buffer = CpuAccessibleBuffer::from_iter(device.clone(),
-                                                          BufferUsage::index_buffer(), false, self.cube.retrieve_ind(chunk_data).into_iter()).unwrap();
+    // the chunks are meshed by the workers (see worker.rs); this only uploads their meshes
+    pub fn onload_data(&mut self, chunk: ChunkID, mesh: ChunkMesh) {
+        for (layer, (vertices, indices)) in mesh.cube.into_iter().enumerate() {
+            let buffers = self.upload(vertices, indices);
+            self.cubes[layer].chunk_data.insert(chunk, buffers);
+        }
+        let buffers = self.upload(mesh.flora.0, mesh.flora.1);
+        self.flora.chunk_data.insert(chunk, buffers);
+        let buffers = self.upload(mesh.liquid.0, mesh.liquid.1);
+        self.liquid.chunk_data.insert(chunk, buffers);
+    }
+
+    // switches between the greedy and the naive mesher; the chunks have to be meshed again
+    pub fn set_mesher(&mut self, mesher: Mesher) {
+        self.mesher = mesher;
+    }
 
-        vec![(vertex_buffer, index_buffer)]
+    pub fn mesher(&self) -> Mesher {
+        self.mesher
+    }
+
+    // the entities are drawn with their own pipeline (see ENTITY_PIPELINE)
+    // - every entity is its own draw from its position, since the vertices are only within a chunk;
+    //   an entity that moved only moves its draw
+    pub fn onload_entities(&mut self, entities: &[Entity]) {
+        let mut entity_data = Vec::new();
+        for entity in entities {
+            let key = (entity.kind, entity.block.id, entity.block.state.clone());
+            if !self.entity_meshes.contains_key(&key) {
+                let (vertices, indices) = Cube::mesh_entity(entity);
+                let buffers = self.upload(vertices, indices);
+                self.entity_meshes.insert(key.clone(), buffers);
+            }
+            entity_data.extend(self.entity_meshes[&key].clone().map(|buffers| (entity.position, buffers)));
+        }
+        self.entity_meshes.retain(|(kind, id, state), _| {
+            entities.iter().any(|entity| entity.kind == *kind && entity.block.id == *id && entity.block.state == *state)
+        });
+        self.cubes[ENTITY_PIPELINE.layer() as usize].onload_entities(entity_data)
+    }
+
+    pub fn offload_chunk(&mut self, chunk: ChunkID) {
+        for cube in self.cubes.iter_mut() {
+            cube.chunk_data.remove(chunk);
+        }
+        self.flora.chunk_data.remove(chunk);
+        self.liquid.chunk_data.remove(chunk);
+    }
+
+    // the buffers to draw, one pair per chunk, in the order of the pipelines; nothing is uploaded here
+    pub fn retrieve_data(&self, chunk_data: &[Chunk]) -> Vec<Draw> {
+        let mut draws = Vec::new();
+        for &pipeline in PIPELINES.iter() {
+            match pipeline {
+                Pipeline::Flora => draws.extend(Self::draws(pipeline, self.flora.chunk_data.visible(chunk_data), chunk_data)),
+                Pipeline::Liquid => draws.extend(Self::draws(pipeline, self.liquid.chunk_data.visible(chunk_data), chunk_data)),
+                cube => draws.extend(Self::draws(cube, self.cubes[cube.layer() as usize].chunk_data.visible(chunk_data), chunk_data)),
+            }
+            if pipeline == ENTITY_PIPELINE {
+                for (origin, (vertices, indices)) in self.cubes[ENTITY_PIPELINE.layer() as usize].entity_data() {
+                    draws.push(Draw { pipeline, origin, buffers: (vertices as Arc<dyn BufferAccess + Send + Sync>, indices) });
+                }
+            }
+        }
+        draws
+    }
+
+    fn draws<'a, V: Send + Sync + 'static>(pipeline: Pipeline, buffers: Vec<(ChunkID, MeshBuffers<V>)>, chunk_data: &'a [Chunk]) -> impl Iterator<Item = Draw> + 'a {
+        buffers.into_iter().filter_map(move |(id, (vertices, indices))| {
+            let chunk = chunk_data.iter().find(|chunk| chunk.id == id)?;
+            let (origin, _, _) = chunk.mesh_data();
+            Some(Draw { pipeline, origin, buffers: (vertices as Arc<dyn BufferAccess + Send + Sync>, indices) })
+        })
+    }
+
+    // moves the translucent draws behind the rest, with the chunks farthest from the eye first, so
+    // every chunk is blended over the ones behind it
+    // - the sort is stable, so the opaque and cutout draws keep the order of their pipelines, and
+    //   within a chunk the translucent blocks go before the liquid
+    // - the translucent faces within a chunk aren't sorted
+    pub fn sort_draws(draws: &mut [Draw], eye: [f32; 3]) {
+        let distance = |draw: &Draw| {
+            let centre = |i: usize| draw.origin[i]+(CHUNK_SIZE/2) as f32-eye[i];
+            centre(0)*centre(0)+centre(1)*centre(1)+centre(2)*centre(2)
+        };
+        let translucent = |draw: &Draw| draw.pipeline.layer() == Layer::Translucent;
+        draws.sort_by(|a, b| {
+            translucent(a).cmp(&translucent(b)).then_with(|| {
+                if translucent(a) {
+                    distance(b).partial_cmp(&distance(a)).unwrap_or(Ordering::Equal)
+                } else {
+                    Ordering::Equal
+                }
+            })
+        });
     }
 
     pub fn retrieve_pipeline(&self,
@@ -75,11 +315,38 @@ impl Meshes {
                              render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
                              dimensions: [u32; 2]
     ) -> Vec<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
-        vec![self.cube.pipeline(device.clone(),render_pass.clone(), dimensions)]
+        PIPELINES.iter().map(|&pipeline| match pipeline {
+            Pipeline::Flora => self.flora.pipeline(device.clone(), render_pass.clone(), dimensions),
+            Pipeline::Liquid => self.liquid.pipeline(device.clone(), render_pass.clone(), dimensions),
+            cube => self.cubes[cube.layer() as usize].pipeline(device.clone(), render_pass.clone(), dimensions),
+        }).collect()
     }
 
-    pub fn cube_sets<'b, U: Send+Sync+'b, A: MemoryPool+Sync+'b>(&self, pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>, sub_buf: &CpuBufferPoolSubbuffer<U, A>) -> Vec<Arc<dyn DescriptorSet+Send+Sync+'b>>
+    // the descriptor sets of every pipeline (see retrieve_pipeline)
+    pub fn mesh_sets<'b, U: Send+Sync+'b, A: MemoryPool+Sync+'b>(&self, pipelines: &[Arc<dyn GraphicsPipelineAbstract + Send + Sync>], sub_buf: &CpuBufferPoolSubbuffer<U, A>) -> Vec<Vec<Arc<dyn DescriptorSet+Send+Sync+'b>>>
     where <A as MemoryPool>::Alloc: Send+Sync {
-        self.cube.descriptors(pipeline.clone(), &sub_buf.clone())
+        PIPELINES.iter().map(|&pipeline| {
+            let layout = pipelines[pipeline.index()].clone();
+            match pipeline {
+                Pipeline::Flora => self.flora.descriptors(layout, sub_buf),
+                Pipeline::Liquid => self.liquid.descriptors(layout, sub_buf),
+                cube => self.cubes[cube.layer() as usize].descriptors(layout, sub_buf),
+            }
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipelines_are_listed_by_their_index() {
+        for (i, pipeline) in PIPELINES.iter().enumerate() {
+            assert_eq!(pipeline.index(), i);
+        }
+        // the translucent pipelines are last, so sorting their draws back doesn't reorder the rest
+        let first = PIPELINES.iter().position(|p| p.layer() == Layer::Translucent).unwrap();
+        assert!(PIPELINES[first..].iter().all(|p| p.layer() == Layer::Translucent));
     }
 }
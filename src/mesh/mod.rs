@@ -1,3 +1,4 @@
 pub mod mesh;
 pub mod cube;
-// pub mod flora;
+pub mod flora;
+pub mod liquid;
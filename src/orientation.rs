@@ -0,0 +1,247 @@
+use crate::mesh::cube::Axis;
+
+use serde::{Serialize, Deserialize};
+use cgmath::Vector3;
+
+
+// the faces in the order of the cube mesh (top, bottom, left, right, front, back)
+pub const NORMALS: [[i32; 3]; 6] = [[0, 1, 0], [0, -1, 0], [-1, 0, 0], [1, 0, 0], [0, 0, -1], [0, 0, 1]];
+// the directions the texture goes along on each face; u is left to right, v is top to bottom
+pub const U: [[i32; 3]; 6] = [[1, 0, 0], [1, 0, 0], [0, 0, -1], [0, 0, 1], [1, 0, 0], [-1, 0, 0]];
+pub const V: [[i32; 3]; 6] = [[0, 0, -1], [0, 0, 1], [0, -1, 0], [0, -1, 0], [0, -1, 0], [0, -1, 0]];
+// the corners of a texture (top left, top right, bottom right, bottom left) by [u][v]
+const CORNERS: [[usize; 2]; 2] = [[0, 3], [1, 2]];
+
+
+// Orientation
+// - a facing is the direction the front face of the block points to
+//     - north is -z, south is +z, east is +x and west is -x
+//     - Facing(North) is the block as it is defined
+// - an axis is the direction the top and bottom faces point along (e.g. logs)
+// - the mesh rotates which texture goes on which face and the texture on the face itself
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub enum Facing {
+    Up,
+    Down,
+    North,
+    South,
+    East,
+    West,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub enum Orientation {
+    Facing(Facing),
+    Axis(Axis),
+}
+
+// how a block gets oriented when it is placed
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Deserialize)]
+pub enum Rotation {
+    #[default]
+    None,
+    Facing,  // any of the six facings (e.g. dispensers)
+    Horizontal,  // only north, south, east and west (e.g. furnaces, stairs)
+    Axis,  // e.g. logs
+}
+
+impl Default for Orientation {
+    fn default() -> Self {
+        Orientation::Facing(Facing::North)
+    }
+}
+
+impl Rotation {
+    // the orientation of a block placed by a player looking in the given direction
+    // - facings point back at the player, axes follow the look direction
+    pub fn orient(&self, look: Vector3<f32>) -> Orientation {
+        let horizontal = if look.x.abs() > look.z.abs() {
+            if look.x > 0.0 {Facing::West} else {Facing::East}
+        } else {
+            if look.z > 0.0 {Facing::North} else {Facing::South}
+        };
+        let vertical = look.y.abs() > look.x.abs().max(look.z.abs());
+
+        match self {
+            Rotation::None => Orientation::default(),
+            Rotation::Horizontal => Orientation::Facing(horizontal),
+            Rotation::Facing if vertical => Orientation::Facing(if look.y > 0.0 {Facing::Down} else {Facing::Up}),
+            Rotation::Facing => Orientation::Facing(horizontal),
+            Rotation::Axis if vertical => Orientation::Axis(Axis::Y),
+            Rotation::Axis => Orientation::Axis(if look.x.abs() > look.z.abs() {Axis::X} else {Axis::Z}),
+        }
+    }
+}
+
+impl Orientation {
+    // the rotation from the block into the world, as where the x, y and z axes end up
+    fn matrix(&self) -> [[i32; 3]; 3] {
+        match self {
+            Orientation::Facing(Facing::North) | Orientation::Axis(Axis::Y) => [[1, 0, 0], [0, 1, 0], [0, 0, 1]],
+            Orientation::Facing(Facing::South) => [[-1, 0, 0], [0, 1, 0], [0, 0, -1]],
+            Orientation::Facing(Facing::East) => [[0, 0, 1], [0, 1, 0], [-1, 0, 0]],
+            Orientation::Facing(Facing::West) => [[0, 0, -1], [0, 1, 0], [1, 0, 0]],
+            Orientation::Facing(Facing::Up) | Orientation::Axis(Axis::Z) => [[1, 0, 0], [0, 0, 1], [0, -1, 0]],
+            Orientation::Facing(Facing::Down) => [[1, 0, 0], [0, 0, -1], [0, 1, 0]],
+            Orientation::Axis(Axis::X) => [[0, -1, 0], [1, 0, 0], [0, 0, 1]],
+        }
+    }
+
+    // the point in the block (within [0, 1]) after turning the block around its centre
+    pub fn rotate(&self, point: [f32; 3]) -> [f32; 3] {
+        let r = self.matrix();
+        let mut out = [0.5; 3];
+        for i in 0..3 {
+            for axis in 0..3 {
+                out[i] += (point[axis]-0.5)*r[axis][i] as f32;
+            }
+        }
+        out
+    }
+
+    // the offset (e.g. to another part of a multi-block structure) after the rotation
+    pub fn offset(&self, offset: [i32; 3]) -> [i32; 3] {
+        let r = self.matrix();
+        let mut out = [0; 3];
+        for i in 0..3 {
+            out[i] = offset[0]*r[0][i] + offset[1]*r[1][i] + offset[2]*r[2][i];
+        }
+        out
+    }
+
+    // for each face of the mesh, the face of the block that ends up there and which of its texture
+    // corners go on the corners of the mesh face
+    pub fn faces(&self) -> [(usize, [usize; 4]); 6] {
+        let r = self.matrix();
+        let rotate = |v: [i32; 3]| {
+            let mut out = [0; 3];
+            for i in 0..3 {
+                out[i] = v[0]*r[0][i] + v[1]*r[1][i] + v[2]*r[2][i];
+            }
+            out
+        };
+        let dot = |a: [i32; 3], b: [i32; 3]| a[0]*b[0] + a[1]*b[1] + a[2]*b[2];
+
+        let mut faces = [(0, [0; 4]); 6];
+        for (face, normal) in NORMALS.iter().enumerate() {
+            // the face of the block that got rotated onto this face
+            let block_face = (0..6).find(|&f| rotate(NORMALS[f]) == *normal).unwrap();
+            let (u, v) = (rotate(U[block_face]), rotate(V[block_face]));
+
+            for (s, row) in CORNERS.iter().enumerate() {
+                for (t, &corner) in row.iter().enumerate() {
+                    // from the corner of the mesh face to the corner of the rotated texture; both in -1 or 1
+                    let (s2, t2) = (s as i32*2-1, t as i32*2-1);
+                    let su = s2*dot(U[face], u) + t2*dot(V[face], u);
+                    let sv = s2*dot(U[face], v) + t2*dot(V[face], v);
+                    faces[face].0 = block_face;
+                    faces[face].1[corner] = CORNERS[((su+1)/2) as usize][((sv+1)/2) as usize];
+                }
+            }
+        }
+        faces
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FACINGS: [Facing; 6] = [Facing::Up, Facing::Down, Facing::North, Facing::South, Facing::East, Facing::West];
+
+    fn orientations() -> Vec<Orientation> {
+        FACINGS.iter().map(|&f| Orientation::Facing(f))
+            .chain([Axis::X, Axis::Y, Axis::Z].iter().map(|&a| Orientation::Axis(a)))
+            .collect()
+    }
+
+    // the direction a facing points to
+    fn direction(facing: Facing) -> [i32; 3] {
+        match facing {
+            Facing::Up => [0, 1, 0],
+            Facing::Down => [0, -1, 0],
+            Facing::North => [0, 0, -1],
+            Facing::South => [0, 0, 1],
+            Facing::East => [1, 0, 0],
+            Facing::West => [-1, 0, 0],
+        }
+    }
+
+    // the point of a face of the block at the given steps along its u and v (in -1, 0 or 1)
+    fn face_point(face: usize, u: i32, v: i32) -> [f32; 3] {
+        let mut point = [0.5; 3];
+        for i in 0..3 {
+            point[i] += (NORMALS[face][i] + u*U[face][i] + v*V[face][i]) as f32/2.0;
+        }
+        point
+    }
+
+    #[test]
+    fn matrices_are_proper_rotations() {
+        for orientation in orientations() {
+            let r = orientation.matrix();
+            let det = r[0][0]*(r[1][1]*r[2][2]-r[1][2]*r[2][1])
+                - r[0][1]*(r[1][0]*r[2][2]-r[1][2]*r[2][0])
+                + r[0][2]*(r[1][0]*r[2][1]-r[1][1]*r[2][0]);
+            assert_eq!(det, 1, "{:?}", orientation);
+            for a in 0..3 {
+                for b in 0..3 {
+                    let dot: i32 = (0..3).map(|i| r[a][i]*r[b][i]).sum();
+                    assert_eq!(dot, (a == b) as i32, "{:?}", orientation);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn the_front_points_where_the_block_faces() {
+        for &facing in FACINGS.iter() {
+            let orientation = Orientation::Facing(facing);
+            assert_eq!(orientation.offset(NORMALS[4]), direction(facing), "{:?}", facing);
+            let front = orientation.rotate(face_point(4, 0, 0));
+            let expected = direction(facing).iter().map(|&d| 0.5+d as f32/2.0).collect::<Vec<_>>();
+            assert_eq!(front.to_vec(), expected, "{:?}", facing);
+        }
+        for (axis, along) in [(Axis::X, 0), (Axis::Y, 1), (Axis::Z, 2)].iter() {
+            let top = Orientation::Axis(*axis).offset(NORMALS[0]);
+            assert_eq!(top[*along].abs(), 1, "{:?}", axis);
+        }
+    }
+
+    #[test]
+    fn faces_follow_the_rotation() {
+        for orientation in orientations() {
+            for (face, &(block_face, corners)) in orientation.faces().iter().enumerate() {
+                // the block face turns onto the mesh face
+                assert_eq!(orientation.rotate(face_point(block_face, 0, 0)), face_point(face, 0, 0), "{:?} face {}", orientation, face);
+
+                // and every texture corner onto the mesh corner it is put on
+                for s in 0..2 {
+                    for t in 0..2 {
+                        let corner = corners[CORNERS[s][t]];
+                        let (bs, bt) = (0..2).flat_map(|s| (0..2).map(move |t| (s, t))).find(|&(s, t)| CORNERS[s][t] == corner).unwrap();
+                        let texture = orientation.rotate(face_point(block_face, bs as i32*2-1, bt as i32*2-1));
+                        assert_eq!(texture, face_point(face, s as i32*2-1, t as i32*2-1), "{:?} face {} corner {}", orientation, face, corner);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn placed_blocks_face_the_player() {
+        let look = |x, y, z| Vector3::new(x, y, z);
+        assert_eq!(Rotation::Horizontal.orient(look(0.2, -0.9, 1.0)), Orientation::Facing(Facing::North));
+        assert_eq!(Rotation::Horizontal.orient(look(0.0, 0.0, -1.0)), Orientation::Facing(Facing::South));
+        assert_eq!(Rotation::Horizontal.orient(look(1.0, 0.0, 0.5)), Orientation::Facing(Facing::West));
+        assert_eq!(Rotation::Horizontal.orient(look(-1.0, 0.0, 0.5)), Orientation::Facing(Facing::East));
+        assert_eq!(Rotation::Facing.orient(look(0.1, -1.0, 0.2)), Orientation::Facing(Facing::Up));
+        assert_eq!(Rotation::Facing.orient(look(0.1, 1.0, 0.2)), Orientation::Facing(Facing::Down));
+        assert_eq!(Rotation::Facing.orient(look(-1.0, 0.5, 0.2)), Orientation::Facing(Facing::East));
+        assert_eq!(Rotation::Axis.orient(look(0.1, -1.0, 0.2)), Orientation::Axis(Axis::Y));
+        assert_eq!(Rotation::Axis.orient(look(-1.0, 0.5, 0.2)), Orientation::Axis(Axis::X));
+        assert_eq!(Rotation::Axis.orient(look(0.3, 0.5, -1.0)), Orientation::Axis(Axis::Z));
+        assert_eq!(Rotation::None.orient(look(1.0, 0.0, 0.0)), Orientation::default());
+    }
+}
@@ -1,8 +1,11 @@
 use crate::world::World;
 use crate::mesh::cube::vs;
+use crate::mesh::mesh::{Meshes, Draw};
 use crate::camera::Camera;
-use crate::texture::TextureAtlas;
+use crate::texture::{TextureAtlas, BLOCK_TEXTURE_DIR, MAX_TILES};
 use crate::ui::UIContext;
+use crate::terrain::WorldType;
+use crate::generator::climate::Tint;
 
 use vulkano;
 use vulkano::device::{Device, Queue};
@@ -15,22 +18,50 @@ use vulkano::swapchain::{Swapchain, SurfaceTransform, ColorSpace, PresentMode, F
 use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
 use vulkano::format::Format;
 use vulkano::framebuffer::Framebuffer;
-use vulkano::buffer::{CpuAccessibleBuffer, TypedBufferAccess};
 use vulkano::instance::PhysicalDevice;
 use vulkano::pipeline::GraphicsPipelineAbstract;
 
 use std::{fmt, thread};
-use std::rc::Rc;
+use std::path::Path;
+use std::time::Instant;
 use std::sync::{Arc, mpsc};
 
 
 pub trait Vertex {}
 
 
+// a corner of a cube mesh face, packed into two words and unpacked by cube.vert
+// - first: the position along x (bits 0 - 10) and y (11 - 21), the face (22 - 24), the ambient
+//   occlusion (25 - 26) and the turn of the texture (27 - 29)
+// - second: the position along z (bits 0 - 10), the biome tint (11 - 12), the tile (13 - 21) and the
+//   climate (22 - 31, see TintMap::climate)
+// - the position is in 1/16 of a block from the origin of the draw (the chunk), up to the far side
+//   of the chunk
+// - the texture coordinates follow from the position along the u and v of the face; the turn is the
+//   texture corner on the top left of the face (0 - 3, see QUAD_CORNERS) and whether the texture is
+//   mirrored (4)
+// - the tint is which colour (none, grass or foliage), and the shader blends it from the climate
+//   of the block corner like Climate::blend
 #[derive(Default, Copy, Clone)]
-pub struct CubeVtx {  // rename: TxtrVtx
+pub struct CubeVtx {
+    pub data: [u32; 2],
+}
+
+#[derive(Default, Copy, Clone)]
+pub struct FloraVtx {
+    pub position: [f32; 3],
+    pub txtr_crd: [f32; 2],  // within the tile
+    pub tile: u32,  // the texture, by tile id (see TextureAtlas::tiles)
+    pub tint: [f32; 3],  // biome tint colour
+}
+
+#[derive(Default, Copy, Clone)]
+pub struct LiquidVtx {
     pub position: [f32; 3],
-    pub txtr_crd: [f32; 2],
+    pub txtr_crd: [f32; 2],  // within the tile
+    pub tile: u32,  // the texture, by tile id (see TextureAtlas::tiles)
+    pub tint: [f32; 3],  // biome tint colour
+    pub flow: [f32; 2],  // the direction the liquid flows in along the texture; scrolls the texture
 }
 
 #[derive(Default, Copy, Clone)]
@@ -40,14 +71,63 @@ pub struct UIVtx {
 }
 
 impl Vertex for CubeVtx {}
+impl Vertex for FloraVtx {}
+impl Vertex for LiquidVtx {}
 impl Vertex for UIVtx {}
 
 
+impl CubeVtx {
+    // the position is in 1/16 of a block (below 2048), the ao within 0 - 3, the turn within 0 - 7,
+    // the tile below MAX_TILES and the climate below 1024
+    pub fn new(position: [u32; 3], face: usize, ao: u8, turn: u8, tint: Tint, tile: u32, climate: u32) -> Self {
+        // a field out of its range would spill into the next one
+        debug_assert!(position.iter().all(|&p| p < 1 << 11), "position {:?} out of range", position);
+        debug_assert!(face < 6, "face {} out of range", face);
+        debug_assert!(ao < 4, "ao {} out of range", ao);
+        debug_assert!(turn < 8, "turn {} out of range", turn);
+        debug_assert!((tile as usize) < MAX_TILES, "tile {} out of range", tile);
+        debug_assert!(climate < 1 << 10, "climate {} out of range", climate);
+        let tint = match tint {
+            Tint::None => 0,
+            Tint::Grass => 1,
+            Tint::Foliage => 2,
+        };
+        Self {
+            data: [
+                position[0] | position[1] << 11 | (face as u32) << 22 | (ao as u32) << 25 | (turn as u32) << 27,
+                position[2] | tint << 11 | tile << 13 | climate << 22,
+            ],
+        }
+    }
+}
+
 impl fmt::Debug for CubeVtx {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("CubeVtx")
+            .field("data", &self.data)
+            .finish()
+    }
+}
+
+impl fmt::Debug for FloraVtx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FloraVtx")
+            .field("position", &self.position)
+            .field("txtr_crd", &self.txtr_crd)
+            .field("tile", &self.tile)
+            .field("tint", &self.tint)
+            .finish()
+    }
+}
+
+impl fmt::Debug for LiquidVtx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LiquidVtx")
             .field("position", &self.position)
             .field("txtr_crd", &self.txtr_crd)
+            .field("tile", &self.tile)
+            .field("tint", &self.tint)
+            .field("flow", &self.flow)
             .finish()
     }
 }
@@ -61,7 +141,9 @@ impl fmt::Debug for UIVtx {
     }
 }
 
-vulkano::impl_vertex!(CubeVtx, position, txtr_crd);
+vulkano::impl_vertex!(CubeVtx, data);
+vulkano::impl_vertex!(FloraVtx, position, txtr_crd, tile, tint);
+vulkano::impl_vertex!(LiquidVtx, position, txtr_crd, tile, tint, flow);
 vulkano::impl_vertex!(UIVtx, position, color);
 
 
@@ -75,18 +157,18 @@ pub struct Render {
     pipeline: Vec<Arc<dyn GraphicsPipelineAbstract + Send + Sync>>,
 
     recreate: bool, // recreate swapchain
-    vertices: Arc<CpuAccessibleBuffer<[CubeVtx]>>,
-    indices: Arc<CpuAccessibleBuffer<[u32]>>,
+    mesh_data: Vec<Draw>,  // a vertex and index buffer per chunk and pipeline, in drawing order
     pub ui: UIContext,
 
-    textures: Vec<Rc<TextureAtlas>>,
+    textures: Vec<Arc<TextureAtlas>>,
 
     pub world: World,
     pub cam: Camera<vs::ty::Matrix>,
+    clock: Instant,  // the game clock for the texture animations
 }
 
 impl Render {
-    pub fn new(physical: PhysicalDevice, device: Arc<Device>, queue: Arc<Queue>, surface: Arc<Surface<Window>>) -> Self {
+    pub fn new(physical: PhysicalDevice, device: Arc<Device>, queue: Arc<Queue>, surface: Arc<Surface<Window>>, world_type: WorldType) -> Self {
         let caps = surface.capabilities(physical)
             .expect("failed to get surface capabilities");
 
@@ -120,14 +202,13 @@ impl Render {
             }
         ).unwrap());
 
-        let (txtr, future) = TextureAtlas::load(queue.clone(), include_bytes!("../resource/texture/texture2.png").to_vec(), 16);
+        let (txtr, future) = TextureAtlas::build(queue.clone(), Path::new(BLOCK_TEXTURE_DIR));
 
         let cam = Camera::new(device.clone(), 0.1, 0.125);
-        let mut world = World::new(String::from("World 0"), device.clone(), queue.clone(), txtr.clone());
+        let mut world = World::new(String::from("World 0"), world_type, device.clone(), queue.clone(), txtr.clone());
         // world.instantiate();
 
-        let mut mesh_data = world.mesh_datas(device.clone());
-        let (v, i) = mesh_data.pop().unwrap();
+        let mesh_data = world.mesh_datas();
 
         Self {
             previous_frame: Some(Box::new(sync::now(device.clone()).join(future)) as Box<dyn GpuFuture>),
@@ -138,8 +219,7 @@ impl Render {
 
             recreate: false,
             pipeline: world.mesh_pipelines(device.clone(), renderpass.clone(), dimensions),
-            vertices: v,
-            indices: i,
+            mesh_data,
             ui: UIContext::new(device.clone()),
 
             textures: vec![
@@ -148,6 +228,7 @@ impl Render {
 
             world: world,
             cam: cam,
+            clock: Instant::now(),
         }
     }
 
@@ -155,11 +236,13 @@ impl Render {
         // cleans the buffer
         self.previous_frame.as_mut().unwrap().cleanup_finished();
 
-        if let Some(chunk_loaded) = self.world.update(&self.cam) {
-            let mut mesh_data = self.world.mesh_datas(device.clone());
-            let (v, i) = mesh_data.pop().unwrap();
-            self.vertices = v;
-            self.indices = i;
+        if let Some(_chunk_loaded) = self.world.update(&self.cam) {
+            self.mesh_data = self.world.mesh_datas();
+        }
+        // the frame waits for the meshes to be copied into their buffers
+        if let Some(uploads) = self.world.take_uploads() {
+            let previous_frame = self.previous_frame.take().unwrap();
+            self.previous_frame = Some(Box::new(previous_frame.join(uploads)) as Box<dyn GpuFuture>);
         }
 
         if self.recreate {
@@ -181,8 +264,12 @@ impl Render {
             self.recreate = false;
         }
 
+        // the camera moves every frame, so the translucent chunks are sorted again every frame
+        let eye = self.cam.position;
+        Meshes::sort_draws(&mut self.mesh_data, [eye.x, eye.y, eye.z]);
+
         let sub_buf = self.cam.mat_buf(dimensions);
-        let sets = self.world.cube_sets(self.pipeline[0].clone(), &sub_buf);
+        let sets = self.world.mesh_sets(&self.pipeline, &sub_buf);
 
         let (image_num, suboptimal, acquire_future) = match swapchain::acquire_next_image(self.swapchain.clone(), None) {
             Ok(r) => r,
@@ -195,14 +282,22 @@ impl Render {
 
         if suboptimal { self.recreate = true; }
 
-        println!("Number of vertices rendering: {:?}", self.vertices.clone().len());
+        // wraps around every hour to keep the precision of the float
+        let time = (self.clock.elapsed().as_secs_f64()%3600.0) as f32;
 
         let (vbo, ibo) = self.ui.render(device.clone());
         let ui_pipeline = self.ui.pipeline(device.clone(), dimensions, self.renderpass.clone());
 
-        let command_buffer = AutoCommandBufferBuilder::primary_one_time_submit(device.clone(), queue.family()).unwrap()
-            .begin_render_pass(self.framebuffer[image_num].clone(), false, vec![[0.1, 0.3, 1.0, 1.0].into(), 1f32.into()]).unwrap()
-            .draw_indexed(self.pipeline[0].clone(), &DynamicState::none(), vec!(self.vertices.clone()), self.indices.clone(), sets.clone(), ()).unwrap()
+        let mut builder = AutoCommandBufferBuilder::primary_one_time_submit(device.clone(), queue.family()).unwrap()
+            .begin_render_pass(self.framebuffer[image_num].clone(), false, vec![[0.1, 0.3, 1.0, 1.0].into(), 1f32.into()]).unwrap();
+        // a draw per chunk, so a chunk that changed doesn't need the others uploaded again
+        for draw in self.mesh_data.iter() {
+            let (vertices, indices) = &draw.buffers;
+            let constants = vs::ty::PushConstants { origin: draw.origin, time };
+            builder = builder.draw_indexed(self.pipeline[draw.pipeline.index()].clone(), &DynamicState::none(), vec!(vertices.clone()),
+                                           indices.clone(), sets[draw.pipeline.index()].clone(), constants).unwrap();
+        }
+        let command_buffer = builder
             .draw_indexed(ui_pipeline.clone(), &DynamicState::none(), vec!(vbo.clone()), ibo.clone(), (), ()).unwrap()
             .end_render_pass().unwrap()
             .build().unwrap();
@@ -0,0 +1,198 @@
+use crate::block::Block;
+use crate::orientation::Orientation;
+
+use serde::Deserialize;
+
+
+// the horizontal neighbours a shape can connect to: north (-z), south (+z), east (+x), west (-x)
+pub const CONNECTIONS: [[i64; 3]; 4] = [[0, 0, -1], [0, 0, 1], [1, 0, 0], [-1, 0, 0]];
+
+
+// Block Shapes
+// - a shape is a set of axis-aligned boxes within the block, in [0, 1] on every axis
+// - the boxes are defined for Facing(North) and rotated with the orientation of the block
+// - fences, walls and panes have a post in the middle and connect to the same shape or full
+//   blocks next to them
+// - the faces a shape fully covers (see `covers`) are the only faces that hide the faces of their
+//   neighbours
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Deserialize)]
+pub enum Shape {
+    #[default]
+    Full,
+    Slab,  // bottom half
+    Stairs,  // a slab with a step on the back half
+    Fence,
+    Wall,
+    Pane,  // thin glass panes, iron bars, etc.
+    Door,  // a thin panel on the front; turned a quarter around when open
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Aabb {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Aabb {
+    pub const fn new(min: [f32; 3], max: [f32; 3]) -> Self {
+        Self { min, max }
+    }
+
+    fn rotate(&self, orientation: Orientation) -> Self {
+        let (a, b) = (orientation.rotate(self.min), orientation.rotate(self.max));
+        Self {
+            min: [a[0].min(b[0]), a[1].min(b[1]), a[2].min(b[2])],
+            max: [a[0].max(b[0]), a[1].max(b[1]), a[2].max(b[2])],
+        }
+    }
+
+    // the box turned around the vertical axis of the block so that north ends up at the connection
+    fn turn(&self, connection: usize) -> Self {
+        let corner = |p: [f32; 3]| match connection {
+            0 => p,
+            1 => [1.0-p[0], p[1], 1.0-p[2]],
+            2 => [1.0-p[2], p[1], p[0]],
+            _ => [p[2], p[1], 1.0-p[0]],
+        };
+        let (a, b) = (corner(self.min), corner(self.max));
+        Self {
+            min: [a[0].min(b[0]), a[1].min(b[1]), a[2].min(b[2])],
+            max: [a[0].max(b[0]), a[1].max(b[1]), a[2].max(b[2])],
+        }
+    }
+}
+
+impl Shape {
+    // the post (or the whole shape) of the unconnected shape
+    fn base(&self) -> &'static [Aabb] {
+        const FULL: [Aabb; 1] = [Aabb::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0])];
+        const SLAB: [Aabb; 1] = [Aabb::new([0.0, 0.0, 0.0], [1.0, 0.5, 1.0])];
+        const STAIRS: [Aabb; 2] = [Aabb::new([0.0, 0.0, 0.0], [1.0, 0.5, 1.0]), Aabb::new([0.0, 0.5, 0.5], [1.0, 1.0, 1.0])];
+        const FENCE: [Aabb; 1] = [Aabb::new([0.375, 0.0, 0.375], [0.625, 1.0, 0.625])];
+        const WALL: [Aabb; 1] = [Aabb::new([0.25, 0.0, 0.25], [0.75, 1.0, 0.75])];
+        const PANE: [Aabb; 1] = [Aabb::new([0.4375, 0.0, 0.4375], [0.5625, 1.0, 0.5625])];
+        const DOOR: [Aabb; 1] = [Aabb::new([0.0, 0.0, 0.0], [1.0, 1.0, 0.1875])];
+
+        match self {
+            Shape::Full => &FULL,
+            Shape::Slab => &SLAB,
+            Shape::Stairs => &STAIRS,
+            Shape::Fence => &FENCE,
+            Shape::Wall => &WALL,
+            Shape::Pane => &PANE,
+            Shape::Door => &DOOR,
+        }
+    }
+
+    // the boxes reaching from the post towards a northern connection
+    fn arm(&self) -> &'static [Aabb] {
+        const FENCE: [Aabb; 2] = [
+            Aabb::new([0.4375, 0.375, 0.0], [0.5625, 0.5625, 0.375]),
+            Aabb::new([0.4375, 0.75, 0.0], [0.5625, 0.9375, 0.375]),
+        ];
+        const WALL: [Aabb; 1] = [Aabb::new([0.3125, 0.0, 0.0], [0.6875, 0.8125, 0.25])];
+        const PANE: [Aabb; 1] = [Aabb::new([0.4375, 0.0, 0.0], [0.5625, 1.0, 0.4375])];
+
+        match self {
+            Shape::Fence => &FENCE,
+            Shape::Wall => &WALL,
+            Shape::Pane => &PANE,
+            _ => &[],
+        }
+    }
+
+    // the boxes of the shape; connected is in the order of CONNECTIONS
+    pub fn boxes(&self, orientation: Orientation, connected: [bool; 4]) -> Vec<Aabb> {
+        let mut boxes: Vec<Aabb> = self.base().iter().map(|b| b.rotate(orientation)).collect();
+        for (connection, _) in connected.iter().enumerate().filter(|(_, &c)| c) {
+            boxes.extend(self.arm().iter().map(|b| b.turn(connection)));
+        }
+        boxes
+    }
+
+    // whether the shape reaches out to its neighbours (fences, walls and panes)
+    pub fn connecting(&self) -> bool {
+        !self.arm().is_empty()
+    }
+
+    // whether this shape connects to the neighbouring block
+    pub fn connects(&self, neighbour: &Block) -> bool {
+        self.connecting() && (neighbour.shape == *self || (neighbour.shape == Shape::Full && !neighbour.transparent && neighbour.id != "air"))
+    }
+
+    // whether the face (in the face order of the cube mesh) of the block is fully covered
+    pub fn covers(&self, orientation: Orientation, face: usize) -> bool {
+        if *self == Shape::Full {
+            return true;
+        }
+
+        // the faces before the rotation: top, bottom, left, right, front, back
+        let covered = match self {
+            Shape::Full => [true; 6],
+            Shape::Slab => [false, true, false, false, false, false],
+            Shape::Stairs => [false, true, false, false, false, true],
+            Shape::Door => [false, false, false, false, true, false],  // the panel is on the front
+            Shape::Fence | Shape::Wall | Shape::Pane => [false; 6],
+        };
+        covered[orientation.faces()[face].0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::cube::Axis;
+    use crate::orientation::{Facing, NORMALS};
+
+    const SHAPES: [Shape; 7] = [Shape::Full, Shape::Slab, Shape::Stairs, Shape::Fence, Shape::Wall, Shape::Pane, Shape::Door];
+
+    fn orientations() -> Vec<Orientation> {
+        [Facing::Up, Facing::Down, Facing::North, Facing::South, Facing::East, Facing::West].iter().map(|&f| Orientation::Facing(f))
+            .chain([Axis::X, Axis::Y, Axis::Z].iter().map(|&a| Orientation::Axis(a)))
+            .collect()
+    }
+
+    // whether the boxes fill the whole face of the block; the boxes are on a 1/16 grid, so
+    // checking the middle of every 1/16 square is exact
+    fn filled(boxes: &[Aabb], face: usize) -> bool {
+        let axis = NORMALS[face].iter().position(|&n| n != 0).unwrap();
+        let (a, b) = ((axis+1)%3, (axis+2)%3);
+        let flush = |aabb: &&Aabb| if NORMALS[face][axis] > 0 {aabb.max[axis] == 1.0} else {aabb.min[axis] == 0.0};
+        let flush: Vec<&Aabb> = boxes.iter().filter(flush).collect();
+        (0..16).all(|i| (0..16).all(|j| {
+            let (p, q) = ((i as f32+0.5)/16.0, (j as f32+0.5)/16.0);
+            flush.iter().any(|aabb| aabb.min[a] < p && p < aabb.max[a] && aabb.min[b] < q && q < aabb.max[b])
+        }))
+    }
+
+    #[test]
+    fn covered_faces_are_filled_by_the_boxes() {
+        for &shape in SHAPES.iter() {
+            for orientation in orientations() {
+                for &connected in [[false; 4], [true; 4]].iter() {
+                    let boxes = shape.boxes(orientation, connected);
+                    for face in 0..6 {
+                        assert_eq!(shape.covers(orientation, face), filled(&boxes, face),
+                                   "{:?} {:?} connected {:?} face {}", shape, orientation, connected, face);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn slabs_and_stairs_cover_their_bottom_and_back() {
+        let north = Orientation::Facing(Facing::North);
+        assert!(Shape::Slab.covers(north, 1));
+        assert!((0..6).filter(|&f| f != 1).all(|f| !Shape::Slab.covers(north, f)));
+        assert!(Shape::Stairs.covers(north, 1) && Shape::Stairs.covers(north, 5));
+        assert!(!Shape::Stairs.covers(north, 4) && !Shape::Stairs.covers(north, 0));
+
+        // turned around, the back of the stairs is on the north side
+        let south = Orientation::Facing(Facing::South);
+        assert!(Shape::Stairs.covers(south, 4) && !Shape::Stairs.covers(south, 5));
+        // and a door only covers the side its panel is on
+        assert!(Shape::Door.covers(north, 4) && !Shape::Door.covers(north, 5));
+    }
+}
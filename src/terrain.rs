@@ -1,53 +1,98 @@
 use crate::block::Block;
-use crate::mesh::mesh::MeshType;
+use crate::blockdef::{load_blocks, BLOCK_DIR};
 use crate::texture::TextureAtlas;
+use crate::generator::TerrainGenerator;
+use crate::generator::mountain::Mountain;
+use crate::generator::island::SkyIsland;
+use crate::generator::climate::{Climate, TintMap};
+use crate::generator::bedrock::{Bedrock, BEDROCK_DEPTH};
+use crate::generator::plants::Plants;
+use crate::world::WORLD_FLOOR;
 
 use std::collections::HashMap;
-use std::rc::Rc;
-use rand::Rng;
+use std::path::Path;
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+
+// the blocks the terrain generators place directly
+const REQUIRED_BLOCKS: &[&str] = &["air", "dirt", "grass", "stone", "granite", "sandstone", "limestone", "slate", "bedrock", "tall_grass", "dandelion", "sapling"];
 
 enum Biome {
     FlatPlains
 }
 
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum WorldType {
+    Mountains,
+    SkyIslands,  // floating islands with void in between
+}
+
+impl WorldType {
+    // the world type by its name on the command line (see main.rs)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mountains" => Some(WorldType::Mountains),
+            "sky_islands" => Some(WorldType::SkyIslands),
+            _ => None,
+        }
+    }
+}
+
 pub struct Terrain {
     blocks: HashMap<&'static str, Block>,
+    generator: Box<dyn TerrainGenerator>,
+    climate: Climate,
+    bedrock: Bedrock,
+    plants: Plants,
 }
 
 impl Terrain {
-    pub fn new(txtr: Rc<TextureAtlas>) -> Self {
-        let mut blockspace = HashMap::new();
-
-        blockspace.insert("air", Block::new(MeshType::Cube, txtr.clone(), "air", &[[4,0], [4,0], [4,0], [4, 0], [4,0], [4,0]], 0, true));
-        blockspace.insert("dirt", Block::new(MeshType::Cube, txtr.clone(), "dirt", &[[2,0], [2,0], [2,0], [2, 0], [2,0], [2,0]], 0, false));
-        blockspace.insert("grass", Block::new(MeshType::Cube, txtr.clone(), "grass", &[[0,0], [2,0], [1,0], [1,0], [1,0], [1,0]], 0, false));
-        blockspace.insert("stone", Block::new(MeshType::Cube, txtr.clone(), "stone", &[[3,0], [3,0], [3,0], [3, 0], [3,0], [3,0]], 0, false));
+    pub fn new(txtr: Arc<TextureAtlas>, world_type: WorldType, seed: u32) -> Self {
+        let blockspace = load_blocks(Path::new(BLOCK_DIR), &txtr).unwrap_or_else(|e| panic!("Invalid block definition: {}", e));
+        for id in REQUIRED_BLOCKS {
+            if !blockspace.contains_key(id) {
+                panic!("Missing block definition: no block with the id \"{}\" in {}", id, BLOCK_DIR);
+            }
+        }
 
         Self {
             blocks: blockspace,
+            generator: match world_type {
+                WorldType::Mountains => Box::new(Mountain::new(seed)),
+                WorldType::SkyIslands => Box::new(SkyIsland::new(seed)),
+            },
+            climate: Climate::new(seed.wrapping_add(100)),
+            bedrock: Bedrock::new(seed.wrapping_add(200)),
+            plants: Plants::new(seed.wrapping_add(300)),
         }
     }
 
-    pub fn generate(&mut self, position: &[u32; 3], size: usize) -> Vec<Block> { // generates in mesh
-        println!("Input size constant pre-check: {:?} Blocks", size);
-        println!("Terrain size allocated: {:?} Blocks", size*size*size);
-
-        let ground_level = 120;
-
-        let mut block_data: Vec<Block> = Vec::with_capacity(size*size*size);
-
-        for x in position[0]..position[0]+size as u32 {
-            let num = rand::thread_rng().gen_range(0, 3);
-            for y in position[1]..position[1]+size as u32 {
-                for z in position[2]..position[2]+size as u32  {
-                    if y >= ground_level-num as u32-1 {
-                        block_data.push(self.blocks["air"].clone());
-                    } else if y >= ground_level-num-2 {
-                        block_data.push(self.blocks["grass"].clone());
-                    } else if y >= ground_level-num-5 {
-                        block_data.push(self.blocks["dirt"].clone());
-                    } else {
-                        block_data.push(self.blocks["stone"].clone());
+    pub fn generate(&self, position: &[u32; 3], size: usize) -> Vec<Block> { // generates in mesh
+        let mut block_data = self.generator.generate(&self.blocks, position, size);
+
+        // the bedrock layer on top of the world floor
+        if position[1] < WORLD_FLOOR+BEDROCK_DEPTH {
+            for x in 0..size {
+                for z in 0..size {
+                    let top = WORLD_FLOOR+self.bedrock.height((position[0] as usize+x) as f64, (position[2] as usize+z) as f64);
+                    // the chunks below the floor are never generated (see World::update)
+                    for y in position[1]..top.min(position[1]+size as u32) {
+                        block_data[x*size*size+(y-position[1]) as usize*size+z] = self.blocks["bedrock"].clone();
+                    }
+                }
+            }
+        }
+
+        // plants on the grass; grass on the top layer of the chunk gets none, since the block above
+        // it is in the chunk above
+        for x in 0..size {
+            for z in 0..size {
+                for y in 1..size {
+                    let (below, index) = (x*size*size+(y-1)*size+z, x*size*size+y*size+z);
+                    if block_data[below].id == "grass" && block_data[index].id == "air" {
+                        if let Some(id) = self.plants.plant(position[0] as i64+x as i64, position[2] as i64+z as i64) {
+                            block_data[index] = self.blocks[id].clone();
+                        }
                     }
                 }
             }
@@ -55,4 +100,19 @@ impl Terrain {
 
         block_data
     }
+
+    // a new instance of the block with the given id
+    pub fn block(&self, id: &str) -> Block {
+        self.blocks[id].clone()
+    }
+
+    // every block, keyed by its id
+    pub fn blocks(&self) -> &HashMap<&'static str, Block> {
+        &self.blocks
+    }
+
+    // biome tint colours for the chunk at position
+    pub fn tint_map(&self, position: &[u32; 3], size: usize) -> TintMap {
+        self.climate.tint_map(position, size)
+    }
 }
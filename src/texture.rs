@@ -4,49 +4,301 @@ use vulkano::sync::NowFuture;
 use vulkano::format::Format;
 use vulkano::device::Queue;
 
-use std::io::Cursor;
-use std::rc::Rc;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::Path;
 use std::sync::Arc;
 
+use serde::Deserialize;
+
+
+pub const BLOCK_TEXTURE_DIR: &str = "resource/texture/block";
+const MISSING_SIZE: u32 = 16;
+pub const CONNECTED_VARIANTS: usize = 16;  // one for every combination of connected edges
+pub const MAX_TILES: usize = 512;  // the size of the tiles uniform in cube.vert (16 KiB, which every device supports)
+
+
+// Texture Atlas
+// - packs every PNG in a folder into a single image when the game starts
+//     - the textures are put into rows (shelves) from the tallest to the shortest
+// - textures are looked up by their file name without the extension (e.g. "grass_top")
+// - names that can't be found get the missing texture (a magenta and black checkerboard)
+// - a texture with a RON file of the same name (e.g. water.ron) is animated
+//     - the PNG is a strip of square frames from top to bottom
+//     - the whole strip is packed, but the texture coordinates are only of the first frame; the
+//       fragment shader moves down the strip by the game clock (see `animation`)
+// - every texture and connected variant is also a tile with an id, which the packed cube vertices
+//   refer to; the meshes upload the tiles for their vertex shaders to look the texture up
+//     - the textures past MAX_TILES get the missing texture
 
 pub struct TextureAtlas {
     pub texture: Arc<ImmutableImage<Format>>,
-    quad_size: u16,
     dimensions: Dimensions,
+    regions: HashMap<String, [u32; 4]>,  // texture name -> x, y, width and height in pixels
+    animations: HashMap<String, Animation>,
+    missing: [u32; 4],
+    tile_ids: HashMap<String, u32>,  // texture name -> tile id; a connected strip gets the id of its first variant
+    pub tiles: Vec<Tile>,  // by tile id
+}
+
+// a tile as the vertex shader reads it: the first frame in the atlas (x, y, width, height) and the
+// animation (see `animation`; the last one is unused)
+pub type Tile = [f32; 8];
+
+// the RON file next to an animated texture
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AnimationFile {
+    frame_time: f32,  // in seconds
+}
+
+#[derive(Copy, Clone)]
+struct Animation {
+    frames: u32,
+    frame_time: f32,
+}
+
+// a decoded texture
+struct Image {
+    name: String,
+    width: u32,
+    height: u32,
+    data: Vec<u8>,  // rgba
+    animation: Option<Animation>,
 }
 
 impl TextureAtlas {
-    pub fn load(queue: Arc<Queue>, img_bytes: Vec<u8>, quad_size: u16) -> (Rc<TextureAtlas>, CommandBufferExecFuture<NowFuture, AutoCommandBuffer>) {
-        let cursor = Cursor::new(img_bytes);
-        let decoder = png::Decoder::new(cursor);
-        let (info, mut reader) = decoder.read_info().unwrap();
-        let dimensions = Dimensions::Dim2d { width: info.width, height: info.height };
-        let mut image_data = Vec::new();
-        image_data.resize((info.width * info.height * 4) as usize, 0);
-        reader.next_frame(&mut image_data).unwrap();
+    pub fn build(queue: Arc<Queue>, dir: &Path) -> (Arc<TextureAtlas>, CommandBufferExecFuture<NowFuture, AutoCommandBuffer>) {
+        let mut images = Vec::new();
+        match fs::read_dir(dir) {
+            Ok(entries) => {
+                let mut paths: Vec<_> = entries.filter_map(|e| e.ok().map(|e| e.path())).collect();
+                paths.sort();
+                for path in paths.iter().filter(|p| p.extension().is_some_and(|ext| ext == "png")) {
+                    match Self::decode(path) {
+                        Ok(image) => images.push(image),
+                        Err(e) => println!("Failed to load the texture {:?}: {}", path, e),
+                    }
+                }
+            },
+            Err(e) => println!("Failed to read the texture folder {:?}: {}", dir, e),
+        }
+        images.push(Self::checkerboard());
+
+        // shelf packing; the atlas is a square with a power of 2 width that fits all the textures
+        images.sort_by(|a, b| b.height.cmp(&a.height).then(a.name.cmp(&b.name)));
+        let area: u32 = images.iter().map(|i| i.width*i.height).sum();
+        let mut width = images.iter().map(|i| i.width).max().unwrap().max((area as f64).sqrt().ceil() as u32).next_power_of_two();
+        let placements = loop {
+            match Self::pack(&images, width) {
+                Some(placements) => break placements,
+                None => width *= 2,
+            }
+        };
+
+        let mut image_data = vec![0u8; (width*width*4) as usize];
+        let mut regions = HashMap::new();
+        let mut animations = HashMap::new();
+        let mut missing = [0; 4];
+        for (image, &[x, y]) in images.iter().zip(placements.iter()) {
+            for row in 0..image.height {
+                let src = (row*image.width*4) as usize;
+                let dst = (((y+row)*width+x)*4) as usize;
+                image_data[dst..dst+(image.width*4) as usize].copy_from_slice(&image.data[src..src+(image.width*4) as usize]);
+            }
 
+            let region = [x, y, image.width, image.height];
+            if image.name.is_empty() {
+                missing = region;
+            } else {
+                regions.insert(image.name.clone(), region);
+                if let Some(animation) = image.animation {
+                    animations.insert(image.name.clone(), animation);
+                }
+            }
+        }
+        println!("Texture atlas: {} textures in {}x{}", regions.len(), width, width);
+
+        let dimensions = Dimensions::Dim2d { width, height: width };
         let (texture, tex_future) = ImmutableImage::from_iter(
             image_data.iter().cloned(), dimensions, Format::R8G8B8A8Unorm, queue.clone()
             ).unwrap();
 
-        (
-            Rc::new(TextureAtlas {
-                texture: texture,
-                quad_size: quad_size,
-                dimensions: dimensions,
+        let mut atlas = TextureAtlas {
+            texture: texture,
+            dimensions: dimensions,
+            regions,
+            animations,
+            missing,
+            tile_ids: HashMap::new(),
+            tiles: Vec::new(),
+        };
+        atlas.index_tiles();
+        (Arc::new(atlas), tex_future)
+    }
+
+    // gives every texture and connected variant a tile id, in the order of the names; the missing
+    // texture is tile 0
+    fn index_tiles(&mut self) {
+        let tile = |coord: [[f32; 2]; 4], anim: [f32; 3]| {
+            [coord[0][0], coord[0][1], coord[2][0]-coord[0][0], coord[2][1]-coord[0][1], anim[0], anim[1], anim[2], 0.0]
+        };
+        self.tiles.push(tile(self.texture_coord(""), [1.0, 0.0, 0.0]));
+        let mut names: Vec<String> = self.regions.keys().cloned().collect();
+        names.sort();
+        for name in names {
+            let connected = if name.ends_with("_connected") {self.connected_coord(&name[..name.len()-"_connected".len()])} else {None};
+            let tiles: Vec<Tile> = match connected {
+                Some(variants) => variants.into_iter().map(|coord| tile(coord, [1.0, 0.0, 0.0])).collect(),
+                None => vec![tile(self.texture_coord(&name), self.animation(&name))],
+            };
+            if self.tiles.len()+tiles.len() > MAX_TILES {
+                println!("Warning: no more room for the tiles of \"{}\" (at most {}); using the missing texture", name, MAX_TILES);
+                continue;
+            }
+            self.tile_ids.insert(name, self.tiles.len() as u32);
+            self.tiles.extend(tiles);
+        }
+    }
+
+    // the position of each image in the atlas; none if they don't fit
+    fn pack(images: &[Image], size: u32) -> Option<Vec<[u32; 2]>> {
+        let mut placements = Vec::with_capacity(images.len());
+        let (mut x, mut y, mut shelf) = (0, 0, 0);
+        for image in images {
+            if x+image.width > size {
+                x = 0;
+                y += shelf;
+                shelf = 0;
+            }
+            if y+image.height > size {
+                return None;
+            }
+            placements.push([x, y]);
+            x += image.width;
+            shelf = shelf.max(image.height);
+        }
+        Some(placements)
+    }
+
+    fn decode(path: &Path) -> Result<Image, png::DecodingError> {
+        let mut decoder = png::Decoder::new(File::open(path)?);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let (info, mut reader) = decoder.read_info()?;
+        let mut buffer = vec![0; info.buffer_size()];
+        reader.next_frame(&mut buffer)?;
+
+        // everything into rgba
+        let data = match info.color_type {
+            png::ColorType::RGBA => buffer,
+            png::ColorType::RGB => buffer.chunks(3).flat_map(|p| vec![p[0], p[1], p[2], 255]).collect(),
+            png::ColorType::GrayscaleAlpha => buffer.chunks(2).flat_map(|p| vec![p[0], p[0], p[0], p[1]]).collect(),
+            _ => buffer.iter().flat_map(|&p| vec![p, p, p, 255]).collect(),
+        };
+
+        Ok(Image {
+            name: path.file_stem().unwrap().to_string_lossy().into_owned(),
+            width: info.width,
+            height: info.height,
+            data,
+            animation: Self::animation_file(path, info.width, info.height),
+        })
+    }
+
+    // the animation of the texture from the RON file next to it, if there is one
+    fn animation_file(path: &Path, width: u32, height: u32) -> Option<Animation> {
+        let file = path.with_extension("ron");
+        let source = fs::read_to_string(&file).ok()?;
+        match ron::de::from_str::<AnimationFile>(&source) {
+            Ok(animation) if height.is_multiple_of(width) && animation.frame_time > 0.0 => Some(Animation {
+                frames: height/width,
+                frame_time: animation.frame_time,
             }),
-            tex_future
-        )
+            Ok(_) => {
+                println!("Invalid animation {:?}: the frames must be square and the frame time positive", file);
+                None
+            },
+            Err(e) => {
+                println!("Invalid animation {:?}: {}", file, e);
+                None
+            },
+        }
+    }
+
+    // the missing texture; the only image without a name
+    fn checkerboard() -> Image {
+        let mut data = Vec::with_capacity((MISSING_SIZE*MISSING_SIZE*4) as usize);
+        for y in 0..MISSING_SIZE {
+            for x in 0..MISSING_SIZE {
+                let magenta = (x/(MISSING_SIZE/2) + y/(MISSING_SIZE/2)).is_multiple_of(2);
+                data.extend_from_slice(if magenta {&[255, 0, 255, 255]} else {&[0, 0, 0, 255]});
+            }
+        }
+        Image { name: String::new(), width: MISSING_SIZE, height: MISSING_SIZE, data, animation: None }
+    }
+
+    // whether there is a texture with the given name
+    pub fn contains(&self, name: &str) -> bool {
+        self.regions.contains_key(name)
     }
 
-    pub fn texture_coord(&self, x: u16, y: u16) -> [[f32; 2]; 4] {
-        let x_norm_start = (x*self.quad_size) as f32/self.dimensions.width() as f32;
-        let y_norm_start = (y*self.quad_size) as f32/self.dimensions.height() as f32;
-        let x_norm_end = ((x+1)*self.quad_size) as f32/self.dimensions.width() as f32;
-        let y_norm_end = ((y+1)*self.quad_size) as f32/self.dimensions.height() as f32;
+    // the texture coordinates of the texture with the given name; the missing texture if there is none
+    pub fn texture_coord(&self, name: &str) -> [[f32; 2]; 4] {
+        let [x, y, width, mut height] = *self.regions.get(name).unwrap_or(&self.missing);
+        if let Some(animation) = self.animations.get(name) {
+            height /= animation.frames;  // only the first frame
+        }
+        let x_norm_start = x as f32/self.dimensions.width() as f32;
+        let y_norm_start = y as f32/self.dimensions.height() as f32;
+        let x_norm_end = (x+width) as f32/self.dimensions.width() as f32;
+        let y_norm_end = (y+height) as f32/self.dimensions.height() as f32;
 
         [[x_norm_start, y_norm_start], [x_norm_end, y_norm_start], [x_norm_end, y_norm_end], [x_norm_start, y_norm_end]]
     }
+
+    // the texture coordinates of the connected variants of the texture, from the strip of square
+    // frames named `<name>_connected`; none if there is no such strip
+    // - the variant is the sum of the edges that connect: 1 top, 2 right, 4 bottom and 8 left
+    pub fn connected_coord(&self, name: &str) -> Option<Vec<[[f32; 2]; 4]>> {
+        let [x, y, width, height] = *self.regions.get(&format!("{}_connected", name))?;
+        if height != width*CONNECTED_VARIANTS as u32 {
+            println!("Invalid connected texture \"{}_connected\": it must be a strip of {} square variants", name, CONNECTED_VARIANTS);
+            return None;
+        }
+
+        let (atlas_width, atlas_height) = (self.dimensions.width() as f32, self.dimensions.height() as f32);
+        Some((0..CONNECTED_VARIANTS as u32).map(|variant| {
+            let (top, bottom) = ((y+variant*width) as f32/atlas_height, (y+(variant+1)*width) as f32/atlas_height);
+            let (left, right) = (x as f32/atlas_width, (x+width) as f32/atlas_width);
+            [[left, top], [right, top], [right, bottom], [left, bottom]]
+        }).collect())
+    }
+
+    // the tile id of the texture with the given name; the missing texture if there is none
+    pub fn tile(&self, name: &str) -> u32 {
+        *self.tile_ids.get(name).unwrap_or(&0)
+    }
+
+    // the tile ids of the connected variants of the texture, in the order of connected_coord;
+    // none if there is no such strip
+    pub fn connected_tiles(&self, name: &str) -> Option<Vec<u32>> {
+        self.connected_coord(name)?;
+        let first = self.tile(&format!("{}_connected", name));
+        Some((first..first+CONNECTED_VARIANTS as u32).collect())
+    }
+
+    // the animation of the texture for the vertices: the frame count, the frame time (in seconds)
+    // and the height of a frame in texture coordinates; a single frame when it isn't animated
+    pub fn animation(&self, name: &str) -> [f32; 3] {
+        match (self.animations.get(name), self.regions.get(name)) {
+            (Some(animation), Some(region)) => [
+                animation.frames as f32,
+                animation.frame_time,
+                (region[3]/animation.frames) as f32/self.dimensions.height() as f32,
+            ],
+            _ => [1.0, 0.0, 0.0],
+        }
+    }
 }
 
 use crate::mesh::cube::CubeFace;
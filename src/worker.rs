@@ -0,0 +1,148 @@
+use crate::chunk::{Chunk, ChunkID, Neighbours, CHUNK_SIZE};
+use crate::block::Block;
+use crate::terrain::Terrain;
+use crate::mesh::cube::Mesher;
+use crate::mesh::mesh::ChunkMesh;
+use crate::generator::climate::TintMap;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread::{self, JoinHandle};
+
+
+const WORKER_COUNT: usize = 3;  // leaves a core for the render thread on a 4 core machine
+
+
+// Workers
+// - a pool of threads that load (or generate), mesh and save the chunks off the render thread
+// - the jobs go out through a channel all the workers take from, and the finished chunks and
+//   meshes come back through another one; the world picks them up in its update
+// - the workers only get what they need with the job (the terrain is shared and never changes),
+//   so nothing in the world is locked while they work
+// - a modified chunk that unloads goes to them to be saved; the world doesn't load it again before
+//   it is, and the saves nobody started when the workers stop are done before they are gone
+
+pub enum Job {
+    Load(ChunkID),
+    Mesh {
+        id: ChunkID,
+        revision: u32,  // the revision of the chunk when it was sent
+        mesher: Mesher,
+        position: [f32; 3],
+        blocks: Arc<Vec<Block>>,
+        tints: Arc<TintMap>,
+        neighbours: Neighbours,
+    },
+    Save(Chunk),
+}
+
+pub enum Done {
+    Loaded(Chunk),
+    Meshed {
+        id: ChunkID,
+        revision: u32,
+        mesh: ChunkMesh,
+    },
+    Saved(ChunkID),
+}
+
+pub struct Workers {
+    jobs: Option<mpsc::Sender<Job>>,  // dropped first when the workers are, so they stop
+    waiting: Arc<Mutex<mpsc::Receiver<Job>>>,  // the workers' end of the jobs
+    done: mpsc::Receiver<Done>,
+    threads: Vec<JoinHandle<()>>,
+    save_dir: PathBuf,
+}
+
+impl Workers {
+    pub fn new(terrain: Arc<Terrain>, save_dir: PathBuf) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        let (done_sender, done) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        let threads = (0..WORKER_COUNT).map(|index| {
+            let job_receiver = job_receiver.clone();
+            let done_sender = done_sender.clone();
+            let terrain = terrain.clone();
+            let save_dir = save_dir.clone();
+            thread::Builder::new().name(format!("worker {}", index)).spawn(move || {
+                loop {
+                    // the lock is only held while waiting for the job, not while doing it
+                    let job = job_receiver.lock().unwrap().recv();
+                    let done = match job {
+                        Ok(Job::Load(id)) => Done::Loaded(Self::load(&terrain, &save_dir, id)),
+                        Ok(Job::Mesh { id, revision, mesher, position, blocks, tints, neighbours }) => {
+                            let mesh = ChunkMesh::new(mesher, position, &blocks, &tints, &neighbours);
+                            Done::Meshed { id, revision, mesh }
+                        },
+                        Ok(Job::Save(chunk)) => {
+                            Self::save(&save_dir, &chunk);
+                            Done::Saved(chunk.id)
+                        },
+                        Err(_) => break,  // the world is gone
+                    };
+                    if done_sender.send(done).is_err() {
+                        break;
+                    }
+                }
+            }).expect("failed to spawn a worker thread")
+        }).collect();
+
+        Self {
+            jobs: Some(jobs),
+            waiting: job_receiver,
+            done,
+            threads,
+            save_dir,
+        }
+    }
+
+    // gives the job to the workers; it comes back if they all stopped
+    pub fn send(&self, job: Job) -> Result<(), Job> {
+        match &self.jobs {
+            Some(jobs) => jobs.send(job).map_err(|e| e.0),
+            None => Err(job),
+        }
+    }
+
+    // the jobs that got done since the last time, without waiting for the rest
+    pub fn finished(&self) -> Vec<Done> {
+        self.done.try_iter().collect()
+    }
+
+    // modified chunks are loaded from the save, the rest are generated
+    fn load(terrain: &Terrain, save_dir: &Path, id: ChunkID) -> Chunk {
+        let position = [id.0*CHUNK_SIZE as u32, id.1*CHUNK_SIZE as u32, id.2*CHUNK_SIZE as u32];
+        if Chunk::file(save_dir, id).exists() {
+            match Chunk::load(save_dir, id, terrain.blocks(), terrain.tint_map(&position, CHUNK_SIZE)) {
+                Ok(chunk) => return chunk,
+                Err(e) => println!("Failed to load the saved chunk {:?}: {}", [id.0, id.1, id.2], e),
+            }
+        }
+        Chunk::new(id, position, terrain.generate(&position, CHUNK_SIZE), terrain.tint_map(&position, CHUNK_SIZE))
+    }
+
+    fn save(save_dir: &Path, chunk: &Chunk) {
+        if let Err(e) = fs::create_dir_all(save_dir).and_then(|_| chunk.save(save_dir)) {
+            println!("Failed to save chunk {:?}: {}", [chunk.id.0, chunk.id.1, chunk.id.2], e);
+        }
+    }
+}
+
+impl Drop for Workers {
+    fn drop(&mut self) {
+        // the jobs nobody started are thrown away instead of waited for, but the chunks still
+        // have to be saved
+        self.jobs = None;
+        let waiting: Vec<Job> = self.waiting.lock().unwrap().try_iter().collect();
+        for job in waiting {
+            if let Job::Save(chunk) = job {
+                Self::save(&self.save_dir, &chunk);
+            }
+        }
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
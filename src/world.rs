@@ -1,13 +1,16 @@
-use crate::chunk::{Chunk, ChunkID};
+use crate::chunk::{Chunk, ChunkID, Neighbours};
 use crate::chunk::CHUNK_SIZE;
 use crate::camera::Camera;
 use crate::camera::CHUNK_RADIUS;
 use crate::texture::TextureAtlas;
-use crate::renderer::CubeVtx;
-use crate::terrain::Terrain;
-use crate::mesh::mesh::Meshes;
+use crate::terrain::{Terrain, WorldType};
+use crate::mesh::mesh::{Meshes, Draw, MeshType};
+use crate::mesh::cube::Mesher;
+use crate::block::Block;
+use crate::entity::{Entity, EntityKind, Ground};
+use crate::shape::Shape;
+use crate::worker::{Workers, Job, Done};
 
-use vulkano::buffer::CpuAccessibleBuffer;
 use vulkano::device::Device;
 use vulkano::device::Queue;
 use vulkano::pipeline::{GraphicsPipelineAbstract};
@@ -16,39 +19,129 @@ use vulkano::image::{SwapchainImage};
 use vulkano::buffer::cpu_pool::CpuBufferPoolSubbuffer;
 use vulkano::memory::MemoryPool;
 use vulkano::descriptor::DescriptorSet;
+use vulkano::sync::GpuFuture;
 
 use std::sync::Arc;
-use std::rc::Rc;
-use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::mem;
+use std::time::Instant;
 
 use winit::window::Window;
+use cgmath::{Point3, Vector3, InnerSpace};
+use serde::{Serialize, Deserialize};
+use rand::Rng;
 
 // world file
 // where block storage and block creation happen
 // and also where would the world state go
 // and terrain generation (maybe separate file)
 
-// height limit ~= 512 Block
+pub const WORLD_HEIGHT: u32 = 512;  // height limit in blocks
+pub const WORLD_FLOOR: u32 = 0;  // the lowest block of the world; everything below is solid
+pub const SAVE_DIR: &str = "saves";  // every world is saved in a folder named after it in here
+const MAX_TICK: f32 = 0.1;  // the longest time step of the entities, so a slow frame doesn't make them skip blocks
 
 pub struct World {
     pub name: String,
-    meshes: Rc<RefCell<Meshes>>,
-    terrain: Terrain,
+    meshes: Meshes,
+    terrain: Arc<Terrain>,  // shared with the workers
+    workers: Workers,
     chunks: Vec<Chunk>,
     loaded_chunks: Vec<ChunkID>,
+    loading: Vec<ChunkID>,  // sent to the workers to be loaded
+    saving: Vec<ChunkID>,  // sent to the workers to be saved; not loaded again before they are
+    changed: bool,  // a block changed since the last update
+    save_dir: PathBuf,
+    info: WorldInfo,
+    updates: Vec<[i64; 3]>,  // the blocks that got a neighbour changed since the last update
+    entities: Vec<Entity>,
+    tick: Instant,  // the last entity update
+}
+
+// saved in world.ron; the chunks that were never modified are generated again from the seed
+#[derive(Copy, Clone, Serialize, Deserialize)]
+struct WorldInfo {
+    seed: u32,
+    world_type: WorldType,
+}
+
+// the result of a raycast
+pub struct RayHit {
+    pub block: [i64; 3],  // the block that was hit
+    pub normal: [i64; 3],  // the face that was hit; zero when the ray started inside the block
 }
 
 impl World {
     // create a new world
-    pub fn new(name: String, device: Arc<Device>, queue: Arc<Queue>, txtr: Rc<TextureAtlas>) -> Self {
+    // a world that was saved before keeps its own seed and world type; a new one saves its world
+    // info right away, so the world type sticks
+    pub fn new(name: String, world_type: WorldType, device: Arc<Device>, queue: Arc<Queue>, txtr: Arc<TextureAtlas>) -> Self {
+        let save_dir = Path::new(SAVE_DIR).join(&name);
+        let saved = fs::read_to_string(save_dir.join("world.ron")).ok().and_then(|data| {
+            match ron::de::from_str::<WorldInfo>(&data) {
+                Ok(info) => Some(info),
+                Err(e) => {
+                    // keep the corrupt file around instead of overwriting it
+                    println!("Failed to load the saved world info {:?}: {}", save_dir, e);
+                    if let Err(e) = fs::rename(save_dir.join("world.ron"), save_dir.join("world.ron.invalid")) {
+                        println!("Failed to move the invalid world info: {}", e);
+                    }
+                    None
+                },
+            }
+        });
+        let info = match saved {
+            Some(info) => {
+                println!("Loading the saved world: {:?}", save_dir);
+                if info.world_type != world_type {
+                    println!("The saved world is {:?}; keeping it instead of {:?}", info.world_type, world_type);
+                }
+                info
+            },
+            None => {
+                let info = WorldInfo { seed: rand::thread_rng().gen(), world_type };
+                if let Err(e) = Self::save_info(&save_dir, &info) {
+                    println!("Failed to save the world info: {}", e);
+                }
+                info
+            },
+        };
+
+        let terrain = Arc::new(Terrain::new(txtr.clone(), info.world_type, info.seed));
         World {
             name: name,
-            meshes: Rc::new(RefCell::new(Meshes::new(device.clone(), txtr.clone()))),
-            terrain: Terrain::new(txtr.clone()),
+            meshes: Meshes::new(device.clone(), queue.clone(), txtr.clone()),
+            terrain: terrain.clone(),
+            workers: Workers::new(terrain, save_dir.clone()),
 
             chunks: Vec::new(),
             loaded_chunks: Vec::new(),
+            loading: Vec::new(),
+            saving: Vec::new(),
+            changed: false,
+            save_dir,
+            info,
+            updates: Vec::new(),
+            entities: Vec::new(),
+            tick: Instant::now(),
+        }
+    }
+
+    // saves the world info and every modified chunk
+    pub fn save(&self) -> io::Result<()> {
+        Self::save_info(&self.save_dir, &self.info)?;
+        for chunk in self.chunks.iter().filter(|c| c.modified) {
+            chunk.save(&self.save_dir)?;
         }
+        Ok(())
+    }
+
+    fn save_info(save_dir: &Path, info: &WorldInfo) -> io::Result<()> {
+        fs::create_dir_all(save_dir)?;
+        let info = ron::ser::to_string(info).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        fs::write(save_dir.join("world.ron"), info)
     }
 
     // instantiate the world
@@ -89,58 +182,450 @@ impl World {
     pub fn update<T>(&mut self, camera: &Camera<T>) -> Option<u32> {
         // block position update
 
-        let ply_pos = [camera.chunk_pos()[0] as u32, camera.chunk_pos()[1] as u32, camera.chunk_pos()[2] as u32];
-
-        let mut chunk_loaded = 0;
-        for x in 0..CHUNK_RADIUS as u32*2 {
-            for y in 0..CHUNK_RADIUS as u32*2 {
-                for z in 0..CHUNK_RADIUS as u32*2 {
-                    if  ply_pos[0] as i32+x as i32-CHUNK_RADIUS as i32 > 0 &&
-                        ply_pos[1] as i32+y as i32-CHUNK_RADIUS as i32 > 0 &&
-                        ply_pos[2] as i32+z as i32-CHUNK_RADIUS as i32 > 0 {
-                        if self.load_chunk([
-                            (ply_pos[0]+x-CHUNK_RADIUS as u32),
-                            (ply_pos[1]+y-CHUNK_RADIUS as u32),
-                            (ply_pos[2]+z-CHUNK_RADIUS as u32),
-                        ]) {
-                            chunk_loaded += 1;
-                        }
+        let ply_pos = camera.chunk_pos();
+        let radius = CHUNK_RADIUS as i32;
+        let column_height = (WORLD_HEIGHT as usize/CHUNK_SIZE) as i32;  // chunks in a column of the world
+        // a chunk with all of its blocks below the world floor is never loaded
+        let below_floor = |y: i32| (y as i64+1)*CHUNK_SIZE as i64 <= WORLD_FLOOR as i64;
+
+        for x in -radius..radius {
+            for y in -radius..radius {
+                for z in -radius..radius {
+                    let pos = [ply_pos[0]+x, ply_pos[1]+y, ply_pos[2]+z];
+
+                    // the camera's Y chunk decides which part of the column gets loaded,
+                    // but never below the world floor or above the world height
+                    if pos[0] >= 0 && pos[1] >= 0 && !below_floor(pos[1]) && pos[1] < column_height && pos[2] >= 0 {
+                        self.load_chunk([pos[0] as u32, pos[1] as u32, pos[2] as u32]);
                     }
                 }
             }
         }
 
+        // what the workers finished since the last update
+        let mut loaded = Vec::new();
+        for done in self.workers.finished() {
+            match done {
+                Done::Loaded(chunk) => {
+                    self.loading.retain(|id| id != &chunk.id);
+                    self.loaded_chunks.push(chunk.id);
+                    loaded.push(chunk.id);
+                    self.chunks.push(chunk);
+                },
+                Done::Meshed { id, revision, mesh } => {
+                    // the mesh of a chunk that was sent to be meshed again since is out of date
+                    if self.chunks.iter().any(|c| c.id == id && c.revision == revision) {
+                        self.meshes.onload_data(id, mesh);
+                        self.changed = true;
+                    }
+                },
+                Done::Saved(id) => self.saving.retain(|saved| saved != &id),
+            }
+        }
+        let chunk_loaded = loaded.len() as u32;
+
+        // the new chunks are meshed along with the chunks that were already there around them,
+        // since the faces on their borders can be culled and occluded now
+        let mut remesh = loaded.clone();
+        for id in loaded.iter() {
+            for i in 0..27 {
+                let offset = [i/9-1, i/3%3-1, i%3-1];
+                let other = ChunkID((id.0 as i64+offset[0]) as u32, (id.1 as i64+offset[1]) as u32, (id.2 as i64+offset[2]) as u32);
+                if self.loaded_chunks.contains(&other) && !remesh.contains(&other) {
+                    remesh.push(other);
+                }
+            }
+        }
+        self.remesh(remesh);
+
+        // the chunks out of the radius are unloaded, with a chunk to spare so going back and forth
+        // over a chunk border doesn't load them again and again
+        let far = |id: &&ChunkID| {
+            let pos = [id.0 as i32-ply_pos[0], id.1 as i32-ply_pos[1], id.2 as i32-ply_pos[2]];
+            pos.iter().any(|&p| p < -radius-1 || p > radius)
+        };
+        let far: Vec<ChunkID> = self.loaded_chunks.iter().filter(far).cloned().collect();
+        for id in far {
+            self.offload_chunk(id);
+        }
+
+        // block updates and entities
+        let dt = self.tick.elapsed().as_secs_f32().min(MAX_TICK);
+        self.tick = Instant::now();
+        let spawned = self.block_updates();
+        let moved = self.update_entities(dt);
+        if spawned || moved {
+            self.meshes.onload_entities(&self.entities);
+            self.changed = true;
+        }
+
         // lighting update
         // etc ...
-        if chunk_loaded == 0 {
+        if chunk_loaded == 0 && !self.changed {
             None
         } else {
+            self.changed = false;
             Some(chunk_loaded)
         }
     }
 
-    pub fn load_chunk(&mut self, chunk_pos: [u32; 3]) -> bool {  // returns if the chunk loaded successfully
+    // sends the chunk to the workers to be loaded; it gets meshed once it is back (see update)
+    pub fn load_chunk(&mut self, chunk_pos: [u32; 3]) -> bool {  // returns if the chunk was sent
         let new_id = ChunkID(chunk_pos[0],chunk_pos[1],chunk_pos[2]);
-        if !self.loaded_chunks.contains(&new_id) {
-            let position = [chunk_pos[0]*CHUNK_SIZE as u32, chunk_pos[1]*CHUNK_SIZE as u32, chunk_pos[2]*CHUNK_SIZE as u32];
-            let chunk  = Chunk::new(new_id, position, self.terrain.generate( &position, CHUNK_SIZE));  // &[0,0,0] <- to repeat same terrain generation @ [0,0,0] for each chunk
-            chunk.render(self.meshes.clone());
-
-            self.loaded_chunks.push(chunk.id);
-            self.chunks.push(chunk);
+        if !self.loaded_chunks.contains(&new_id) && !self.loading.contains(&new_id) && !self.saving.contains(&new_id) {
+            if self.workers.send(Job::Load(new_id)).is_err() {
+                println!("Failed to load chunk {:?}: the workers stopped", chunk_pos);
+                return false;
+            }
+            self.loading.push(new_id);
             true
         } else {
             false
         }
     }
 
-    pub fn offload_chunk() {
+    // drops the chunk with its meshes; a modified chunk goes to the workers to be saved
+    pub fn offload_chunk(&mut self, id: ChunkID) {
+        if let Some(index) = self.chunks.iter().position(|c| c.id == id) {
+            let chunk = self.chunks.remove(index);
+            if chunk.modified {
+                match self.workers.send(Job::Save(chunk)) {
+                    Ok(()) => self.saving.push(id),
+                    Err(_) => println!("Failed to save chunk {:?}: the workers stopped", [id.0, id.1, id.2]),
+                }
+            }
+        }
+        self.loaded_chunks.retain(|c| c != &id);
+        self.meshes.offload_chunk(id);
+        self.changed = true;
+    }
+
+    // the chunk and the position inside of the chunk of a block position
+    fn locate(pos: [i64; 3]) -> Option<(ChunkID, [usize; 3])> {
+        if pos[0] < 0 || pos[1] < WORLD_FLOOR as i64 || pos[1] >= WORLD_HEIGHT as i64 || pos[2] < 0 {
+            return None;
+        }
+
+        let size = CHUNK_SIZE as i64;
+        Some((
+            ChunkID((pos[0]/size) as u32, (pos[1]/size) as u32, (pos[2]/size) as u32),
+            [(pos[0]%size) as usize, (pos[1]%size) as usize, (pos[2]%size) as usize],
+        ))
+    }
+
+    // the block at the position; none when it is outside of the world or not loaded
+    pub fn block(&self, pos: [i64; 3]) -> Option<&Block> {
+        let (id, local) = Self::locate(pos)?;
+        self.chunks.iter().find(|c| c.id == id).map(|c| c.block(local))
+    }
+
+    // whether the block at the position is solid for raycasts and physics
+    pub fn solid(&self, pos: [i64; 3]) -> bool {
+        Self::solid_block(pos, self.block(pos))
+    }
+
+    // whether the block (none if it isn't loaded) at the position is solid
+    fn solid_block(pos: [i64; 3], block: Option<&Block>) -> bool {
+        if pos[1] < WORLD_FLOOR as i64 {
+            return true;  // the world floor
+        }
+
+        match block {
+            Some(block) => block.id != "air",
+            None => false,
+        }
+    }
+
+    // finds the first solid block along the ray
+    pub fn raycast(&self, origin: Point3<f32>, direction: Vector3<f32>, reach: f32) -> Option<RayHit> {
+        Self::traverse(origin, direction, reach, |pos| self.solid(pos))
+    }
+
+    // finds the first block along the ray for which solid is true (voxel traversal)
+    fn traverse(origin: Point3<f32>, direction: Vector3<f32>, reach: f32, solid: impl Fn([i64; 3]) -> bool) -> Option<RayHit> {
+        let direction = direction.normalize();
+        let origin = [origin.x, origin.y, origin.z];
+        let direction = [direction.x, direction.y, direction.z];
+
+        let mut block = [origin[0].floor() as i64, origin[1].floor() as i64, origin[2].floor() as i64];
+        let mut normal = [0; 3];
+
+        let mut step = [0; 3];
+        let mut t_max = [f32::INFINITY; 3];  // distance along the ray to the next block border of each axis
+        let mut t_delta = [f32::INFINITY; 3];  // distance along the ray between the block borders of each axis
+        for i in 0..3 {
+            if direction[i] > 0.0 {
+                step[i] = 1;
+                t_max[i] = (block[i] as f32+1.0-origin[i])/direction[i];
+                t_delta[i] = 1.0/direction[i];
+            } else if direction[i] < 0.0 {
+                step[i] = -1;
+                t_max[i] = (origin[i]-block[i] as f32)/-direction[i];
+                t_delta[i] = -1.0/direction[i];
+            }
+        }
+
+        let mut distance = 0.0;
+        while distance <= reach {
+            if solid(block) {
+                return Some(RayHit { block, normal });
+            }
+
+            // steps into the next block through the closest block border
+            let axis = if t_max[0] < t_max[1] {
+                if t_max[0] < t_max[2] {0} else {2}
+            } else {
+                if t_max[1] < t_max[2] {1} else {2}
+            };
+            block[axis] += step[axis];
+            distance = t_max[axis];
+            t_max[axis] += t_delta[axis];
+            normal = [0; 3];
+            normal[axis] = -step[axis];
+        }
+
+        None
+    }
+
+    // replaces the block and re-meshes its chunk; returns false if the chunk isn't loaded
+    pub fn set_block(&mut self, pos: [i64; 3], block: Block) -> bool {
+        self.set_blocks(vec![(pos, block)])
+    }
+
+    // replaces all the blocks at once, or none of them if any of their chunks isn't loaded
+    // - the chunks next to a block on the chunk border get re-meshed as well, since their border
+    //   faces and connected textures depend on it
+    pub fn set_blocks(&mut self, blocks: Vec<([i64; 3], Block)>) -> bool {
+        let mut located = Vec::with_capacity(blocks.len());
+        for (pos, block) in blocks {
+            match Self::locate(pos) {
+                Some((id, local)) if self.loaded_chunks.contains(&id) => located.push((pos, id, local, block)),
+                _ => return false,
+            }
+        }
+
+        let mut remesh = Vec::new();
+        for (pos, id, local, block) in located {
+            if let Some(chunk) = self.chunks.iter_mut().find(|c| c.id == id) {
+                chunk.set_block(local, block);
+            }
+
+            // the chunks of the blocks around it get re-meshed (their faces may be culled or
+            // occluded by the block), and the block and its neighbours get a block update
+            for i in 0..27 {
+                let offset = [i/9-1, i/3%3-1, i%3-1];
+                let neighbour = [pos[0]+offset[0], pos[1]+offset[1], pos[2]+offset[2]];
+                if let Some((other, _)) = Self::locate(neighbour) {
+                    if !remesh.contains(&other) {
+                        remesh.push(other);
+                    }
+                    if offset.iter().map(|o| o.abs()).sum::<i64>() <= 1 && !self.updates.contains(&neighbour) {
+                        self.updates.push(neighbour);
+                    }
+                }
+            }
+        }
+        self.remesh(remesh);
+        self.changed = true;
+        true
+    }
+
+    // meshes every chunk again with the mesher
+    pub fn set_mesher(&mut self, mesher: Mesher) {
+        self.meshes.set_mesher(mesher);
+        self.remesh(self.loaded_chunks.clone());
+        self.changed = true;
+    }
+
+    // sends the chunks to the workers to be meshed again with the chunks around them; the old
+    // meshes are drawn until the new ones are back
+    fn remesh(&mut self, ids: Vec<ChunkID>) {
+        let mesher = self.meshes.mesher();
+        for id in ids {
+            let neighbours = Neighbours::new(id, &self.chunks);
+            if let Some(chunk) = self.chunks.iter_mut().find(|c| c.id == id) {
+                chunk.revision += 1;
+                let (position, blocks, tints) = chunk.mesh_data();
+                if self.workers.send(Job::Mesh { id, revision: chunk.revision, mesher, position, blocks, tints, neighbours }).is_err() {
+                    println!("Failed to mesh chunk {:?}: the workers stopped", (id.0, id.1, id.2));
+                }
+            }
+        }
+    }
+
+    // the blocks of the structure the block at pos belongs to that are still in place; none if any
+    // of its parts is in a chunk that isn't loaded, so the structure is only ever changed as a whole
+    fn structure(&self, pos: [i64; 3]) -> Option<Vec<([i64; 3], Block)>> {
+        let block = self.block(pos)?;
+        let mut parts = Vec::new();
+        for (part_pos, part) in block.structure(pos) {
+            match self.block(part_pos) {
+                Some(other) if other.id == block.id && other.state.part == part => parts.push((part_pos, other.clone())),
+                Some(_) => {},
+                None if Self::locate(part_pos).is_some() => return None,  // not loaded
+                None => {},  // outside of the world
+            }
+        }
+        Some(parts)
+    }
+
+    // the display name of the block
+    pub fn block_name(&self, id: &str) -> &'static str {
+        self.terrain.blocks()[id].name
+    }
+
+    // places a new block into an empty (air) position, oriented by the direction the player looks at
+    // - every part of a multi-block structure needs an empty position as well
+    pub fn place_block(&mut self, pos: [i64; 3], id: &str, look: Vector3<f32>) -> bool {
+        let mut block = self.terrain.block(id);
+        block.state.orientation = block.rotation.orient(look);
+        if block.parts.is_empty() {
+            return match self.block(pos) {
+                Some(other) if other.id == "air" => self.set_block(pos, block),
+                _ => false,
+            };
+        }
+
+        let parts = block.structure(pos);
+        if !parts.iter().all(|(part_pos, _)| self.block(*part_pos).is_some_and(|other| other.id == "air")) {
+            return false;
+        }
+        match parts.into_iter().map(|(part_pos, part)| block.part(part).map(|part| (part_pos, part))).collect() {
+            Some(parts) => self.set_blocks(parts),
+            None => false,
+        }
+    }
+
+    // breaks the block with the rest of its structure; the world floor and indestructible blocks
+    // (bedrock) can't be broken
+    pub fn break_block(&mut self, pos: [i64; 3]) -> bool {
+        match self.block(pos) {
+            Some(block) if block.id != "air" && block.hardness >= 0.0 => {
+                let air = self.terrain.block("air");
+                match self.structure(pos) {
+                    Some(parts) => self.set_blocks(parts.into_iter().map(|(part_pos, _)| (part_pos, air.clone())).collect()),
+                    None => false,
+                }
+            },
+            _ => false,
+        }
+    }
+
+    // uses the block (e.g. opens a door), which flips its toggle property on every part of its
+    // structure; returns false if the block can't be used
+    pub fn use_block(&mut self, pos: [i64; 3]) -> bool {
+        let (toggle, value) = match self.block(pos) {
+            Some(block) => match block.toggle {
+                Some(toggle) => (toggle, if block.toggled() {0u8} else {1}),
+                None => return false,
+            },
+            None => return false,
+        };
+
+        let mut parts = match self.structure(pos) {
+            Some(parts) => parts,
+            None => return false,
+        };
+        for (_, part) in parts.iter_mut() {
+            if let Err(e) = part.set_property(toggle, value) {
+                println!("Failed to use the block: {}", e);
+                return false;
+            }
+        }
+        self.set_blocks(parts)
+    }
+
+    // what a falling entity finds at the position
+    fn ground(&self, pos: [i64; 3]) -> Ground {
+        if pos[1] < WORLD_FLOOR as i64 {
+            return Ground::Solid;
+        }
+
+        match self.block(pos) {
+            Some(block) if block.id == "air" || block.state.liquid || block.mesh == MeshType::Flora => Ground::Passable,
+            Some(_) => Ground::Solid,
+            None => Ground::Unloaded,
+        }
+    }
+
+    // handles the blocks that got a neighbour changed; blocks that fall with nothing below them
+    // turn into falling blocks, and plants with nothing below them drop as items
+    // - an update waits for the chunk of the block, or the chunk below it, to be loaded
+    // - returns whether any entity got spawned
+    fn block_updates(&mut self) -> bool {
+        let mut spawned = false;
+        let mut waiting = Vec::new();
+        for pos in mem::take(&mut self.updates) {
+            let block = match self.block(pos) {
+                Some(block) if block.falls || block.mesh == MeshType::Flora => block.clone(),
+                Some(_) => continue,
+                None if Self::locate(pos).is_some() => {
+                    waiting.push(pos);
+                    continue;
+                },
+                None => continue,
+            };
+            match self.ground([pos[0], pos[1]-1, pos[2]]) {
+                Ground::Passable => {
+                    let air = self.terrain.block("air");
+                    if self.set_block(pos, air) {
+                        self.entities.push(if block.falls {Entity::falling_block(block, pos)} else {Entity::item(block, pos)});
+                        spawned = true;
+                    }
+                },
+                Ground::Unloaded => waiting.push(pos),
+                Ground::Solid => (),
+            }
+        }
+        for pos in waiting {
+            if !self.updates.contains(&pos) {
+                self.updates.push(pos);
+            }
+        }
+        spawned
+    }
+
+    // moves the entities down; a falling block lands as a block again if it can, or drops as an
+    // item if it lands on anything that isn't a full block
+    // - returns whether any entity moved
+    fn update_entities(&mut self, dt: f32) -> bool {
+        let mut moved = false;
+        let mut entities = Vec::with_capacity(self.entities.len());
+        for mut entity in mem::take(&mut self.entities) {
+            let [x, _, z] = entity.block_pos();
+            let (height, resting) = (entity.position[1], entity.resting);
+            let landed = entity.fall(dt, |y| self.ground([x, y, z]));
+            moved |= entity.position[1] != height || entity.resting != resting;
 
+            match (entity.kind, landed) {
+                (_, None) => entities.push(entity),
+                (EntityKind::Item, Some(_)) => {
+                    entity.resting = true;
+                    entities.push(entity);
+                    moved = true;
+                },
+                (EntityKind::FallingBlock, Some(pos)) => {
+                    moved = true;
+                    let below = [pos[0], pos[1]-1, pos[2]];
+                    let full = pos[1]-1 < WORLD_FLOOR as i64 || self.block(below).is_some_and(|b| b.shape == Shape::Full && !b.state.liquid);
+                    if !(full && self.set_block(pos, entity.block.clone())) {
+                        entities.push(Entity::item(entity.block, pos));
+                    }
+                },
+            }
+        }
+        self.entities = entities;
+        moved
     }
 
+    // the copies of the meshes uploaded since the last frame (see Meshes::take_uploads)
+    pub fn take_uploads(&mut self) -> Option<Box<dyn GpuFuture>> {
+        self.meshes.take_uploads()
+    }
 
-    pub fn mesh_datas(&mut self, device: Arc<Device>) -> Vec<(Arc<CpuAccessibleBuffer<[CubeVtx]>>, Arc<CpuAccessibleBuffer<[u32]>>)> {
-        (*self.meshes).borrow_mut().retrieve_data(device.clone(), &self.chunks)
+    // the buffers of the visible chunks; only the chunks that got meshed again were uploaded
+    pub fn mesh_datas(&self) -> Vec<Draw> {
+        self.meshes.retrieve_data(&self.chunks)
     }
 
     pub fn mesh_pipelines(&mut self,
@@ -149,12 +634,46 @@ impl World {
                           dimensions: [u32; 2]
     ) -> Vec<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
         // TODO: this code smells
-        (*self.meshes).borrow_mut().retrieve_pipeline(device.clone(), render_pass.clone(), dimensions)
+        self.meshes.retrieve_pipeline(device.clone(), render_pass.clone(), dimensions)
     }
 
-    pub fn cube_sets<'b, U: Send+Sync+'b, A: MemoryPool+Sync+'b>(&self, pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>, sub_buf: &CpuBufferPoolSubbuffer<U, A>) -> Vec<Arc<dyn DescriptorSet+Send+Sync+'b>>
+    pub fn mesh_sets<'b, U: Send+Sync+'b, A: MemoryPool+Sync+'b>(&self, pipelines: &[Arc<dyn GraphicsPipelineAbstract + Send + Sync>], sub_buf: &CpuBufferPoolSubbuffer<U, A>) -> Vec<Vec<Arc<dyn DescriptorSet+Send+Sync+'b>>>
         where <A as MemoryPool>::Alloc: Send+Sync {
-        (*self.meshes).borrow_mut().cube_sets(pipeline.clone(), &sub_buf.clone())
+        self.meshes.mesh_sets(pipelines, &sub_buf.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::test_block;
+
+    #[test]
+    fn below_the_floor_is_solid() {
+        let floor = WORLD_FLOOR as i64;
+        assert!(World::solid_block([0, floor-1, 0], None));
+        assert!(World::solid_block([-5, floor-64, 12], Some(&test_block("air"))));
+        assert!(!World::solid_block([0, floor, 0], None));
+        assert!(!World::solid_block([0, floor, 0], Some(&test_block("air"))));
+        assert!(World::solid_block([0, floor, 0], Some(&test_block("stone"))));
+    }
+
+    #[test]
+    fn raycasts_hit_the_floor() {
+        // nothing is loaded, so only the floor can be hit
+        let solid = |pos| World::solid_block(pos, None);
+        let origin = Point3::new(3.5, WORLD_FLOOR as f32+4.5, -2.5);
+
+        let hit = World::traverse(origin, Vector3::new(0.0, -1.0, 0.0), 8.0, solid).unwrap();
+        assert_eq!(hit.block, [3, WORLD_FLOOR as i64-1, -3]);
+        assert_eq!(hit.normal, [0, 1, 0]);
+
+        let hit = World::traverse(origin, Vector3::new(1.0, -1.0, 0.0), 16.0, solid).unwrap();
+        assert_eq!(hit.block[1], WORLD_FLOOR as i64-1);
+        assert_eq!(hit.normal, [0, 1, 0]);
+
+        assert!(World::traverse(origin, Vector3::new(0.0, -1.0, 0.0), 3.0, solid).is_none());
+        assert!(World::traverse(origin, Vector3::new(0.0, 1.0, 0.0), 64.0, solid).is_none());
     }
 }
 